@@ -0,0 +1,4 @@
+// sqlx::migrate! embeds the migrations directory, rebuild when it changes
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE api_tokens (
+        id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        name VARCHAR(100) NOT NULL,
+        token_hash VARCHAR(64) NOT NULL UNIQUE,
+        token_prefix VARCHAR(16) NOT NULL,
+        scopes TEXT[] NOT NULL,
+        expires_at TIMESTAMP WITH TIME ZONE,
+        last_used_at TIMESTAMP WITH TIME ZONE,
+        created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE users
+        ADD CONSTRAINT users_role_check CHECK (role IN ('user', 'moderator', 'admin'));
@@ -0,0 +1,9 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS "audit_events";
+
+ALTER TABLE users
+        DROP COLUMN IF EXISTS suspended_at,
+        DROP COLUMN IF EXISTS suspended_until,
+        DROP COLUMN IF EXISTS suspension_reason,
+        DROP COLUMN IF EXISTS password_reset_required;
@@ -0,0 +1,18 @@
+-- Add up migration script here
+ALTER TABLE users
+        ADD COLUMN suspended_at TIMESTAMP WITH TIME ZONE,
+        ADD COLUMN suspended_until TIMESTAMP WITH TIME ZONE,
+        ADD COLUMN suspension_reason TEXT,
+        ADD COLUMN password_reset_required BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE audit_events (
+        id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+        actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+        action VARCHAR(100) NOT NULL,
+        target_id UUID,
+        metadata JSONB NOT NULL DEFAULT '{}',
+        created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX audit_events_actor_id_idx ON audit_events (actor_id);
+CREATE INDEX audit_events_target_id_idx ON audit_events (target_id);
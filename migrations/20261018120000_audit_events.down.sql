@@ -0,0 +1,12 @@
+-- Add down migration script here
+
+DROP TRIGGER IF EXISTS audit_events_no_truncate ON audit_events;
+DROP TRIGGER IF EXISTS audit_events_no_update ON audit_events;
+DROP FUNCTION IF EXISTS audit_events_append_only();
+
+DROP INDEX IF EXISTS audit_events_created_at_idx;
+DROP INDEX IF EXISTS audit_events_action_idx;
+
+ALTER TABLE audit_events
+        DROP COLUMN IF EXISTS ip,
+        DROP COLUMN IF EXISTS user_agent;
@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS "user_identities";
+
+-- '!' never parses as a hash so these accounts can not log in with a password
+UPDATE users SET password = '!' WHERE password IS NULL;
+ALTER TABLE users ALTER COLUMN password SET NOT NULL;
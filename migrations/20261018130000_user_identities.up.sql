@@ -0,0 +1,15 @@
+-- Add up migration script here
+-- accounts created through an identity provider have no password
+ALTER TABLE users ALTER COLUMN password DROP NOT NULL;
+
+CREATE TABLE user_identities (
+        id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        provider VARCHAR(50) NOT NULL,
+        subject VARCHAR(255) NOT NULL,
+        email VARCHAR(255),
+        created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+        UNIQUE (provider, subject)
+);
+
+CREATE INDEX user_identities_user_id_idx ON user_identities (user_id);
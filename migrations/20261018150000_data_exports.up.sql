@@ -0,0 +1,19 @@
+-- Add up migration script here
+CREATE TABLE data_exports (
+        id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        status VARCHAR(20) NOT NULL DEFAULT 'pending'
+                CHECK (status IN ('pending', 'running', 'ready', 'failed')),
+        file_path VARCHAR,
+        created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+        -- bumped by the worker while it builds the export
+        heartbeat_at TIMESTAMP WITH TIME ZONE,
+        completed_at TIMESTAMP WITH TIME ZONE,
+        expires_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX data_exports_user_id_idx ON data_exports (user_id);
+CREATE INDEX data_exports_pending_idx ON data_exports (created_at) WHERE status = 'pending';
+-- one export in progress per user
+CREATE UNIQUE INDEX data_exports_in_progress_idx ON data_exports (user_id)
+        WHERE status IN ('pending', 'running');
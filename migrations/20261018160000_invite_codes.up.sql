@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE invite_codes (
+        id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+        code_hash VARCHAR(64) NOT NULL UNIQUE,
+        code_prefix VARCHAR(16) NOT NULL,
+        created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+        max_uses INTEGER NOT NULL DEFAULT 1 CHECK (max_uses > 0),
+        uses INTEGER NOT NULL DEFAULT 0,
+        expires_at TIMESTAMP WITH TIME ZONE,
+        created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
@@ -0,0 +1,205 @@
+use crate::{
+    audit,
+    client::ClientInfo,
+    config::Config,
+    model::User,
+    password::{self, BreachedPasswords},
+    rbac::Role,
+    response::ApiError,
+    schema::RegisterUserSchema,
+    sessions::revoke_sessions,
+    AppState,
+};
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+use validator::Validate;
+
+// the checks every new password account goes through, shared by registration,
+// the CLI and the seeder so their users behave like real sign ups. Only the
+// seeder may skip the password policy, for throwaway fixture passwords. Hashing
+// is left to the caller, registration checks the proof of work before it
+pub async fn check_new_account(
+    db: &PgPool,
+    config: &Config,
+    breached: &BreachedPasswords,
+    account: &RegisterUserSchema,
+    enforce_policy: bool,
+) -> Result<(), ApiError> {
+    let user_exists: Option<bool> = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE username = $1)",
+        account.username
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+    if user_exists == Some(true) {
+        return Err(ApiError::Fail("username is taken".to_string()));
+    }
+
+    let email_exists: Option<bool> = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE email = $1)",
+        account.email.to_ascii_lowercase()
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+    if email_exists == Some(true) {
+        return Err(ApiError::Fail("Email is taken".to_string()));
+    }
+
+    if account.validate().is_err() {
+        return Err(ApiError::Fail("Email is not valid".to_string()));
+    }
+    if enforce_policy {
+        password::check_policy(
+            config,
+            breached,
+            &account.password,
+            &account.username,
+            &account.email,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+// inserts the user with the default profile, every account starts here
+pub async fn insert_user(
+    conn: &mut PgConnection,
+    username: &str,
+    email: &str,
+    password_hash: Option<&str>,
+    role: Role,
+) -> Result<Uuid, ApiError> {
+    let user_id: Uuid = sqlx::query_scalar!(
+        "INSERT INTO users (username, email, password, role) VALUES ($1, $2, $3, $4) RETURNING id",
+        username,
+        email.to_ascii_lowercase(),
+        password_hash,
+        role.as_str()
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO profiles (user_id, photo, bio) VALUES ($1, $2, $3)",
+        user_id,
+        "default.jpg".to_string(),
+        "".to_string(),
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+    Ok(user_id)
+}
+
+// registration without the http parts, used by the CLI and the seeder
+pub async fn create_account(
+    db: &PgPool,
+    config: &Config,
+    breached: &BreachedPasswords,
+    account: &RegisterUserSchema,
+    role: Role,
+    enforce_policy: bool,
+) -> Result<Uuid, ApiError> {
+    check_new_account(db, config, breached, account, enforce_policy).await?;
+    let password_hash = password::hash(config, &account.password)?;
+    let mut tx = db.begin().await.map_err(|_| ApiError::InternalServerError)?;
+    let user_id = insert_user(
+        &mut tx,
+        &account.username,
+        &account.email,
+        Some(&password_hash),
+        role,
+    )
+    .await?;
+    tx.commit().await.map_err(|_| ApiError::InternalServerError)?;
+    Ok(user_id)
+}
+
+// logging in during the grace period undoes DELETE /user/me
+pub async fn reactivate(data: &AppState, client: &ClientInfo, user: &User) -> Result<(), ApiError> {
+    if user.deactivated_at.is_none() {
+        return Ok(());
+    }
+    sqlx::query!(
+        "UPDATE users SET deactivated_at = NULL, updated_at = NOW() WHERE id = $1",
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    audit::record(
+        &data.db,
+        client,
+        Some(user.id),
+        "account.reactivated",
+        Some(user.id),
+        json!({ "deactivated_at": user.deactivated_at }),
+    )
+    .await;
+    Ok(())
+}
+
+// every way of picking a new password ends here so they share the policy, the
+// old sessions go with the old password
+pub async fn set_password(
+    data: &AppState,
+    client: &ClientInfo,
+    user: &User,
+    new_password: &str,
+    reason: &str,
+) -> Result<(), ApiError> {
+    check_new_password(&data.env, &data.breached_passwords, user, new_password).await?;
+    store_password(data, client, user, new_password, reason).await
+}
+
+// the half of set_password that can refuse, a reset link is only claimed once
+// this passed so a refused password does not use it up
+pub async fn check_new_password(
+    config: &Config,
+    breached: &BreachedPasswords,
+    user: &User,
+    new_password: &str,
+) -> Result<(), ApiError> {
+    if let Some(hash) = user.password.as_deref() {
+        if password::verify(config, hash, new_password) {
+            return Err(ApiError::Fail("new password must differ from the old one".to_string()));
+        }
+    }
+    password::check_policy(config, breached, new_password, &user.username, &user.email).await
+}
+
+// the other half, call check_new_password first
+pub async fn store_password(
+    data: &AppState,
+    client: &ClientInfo,
+    user: &User,
+    new_password: &str,
+    reason: &str,
+) -> Result<(), ApiError> {
+    let hashed_password = password::hash(&data.env, new_password)?;
+    sqlx::query!(
+        "UPDATE users SET password = $1, password_reset_required = FALSE, updated_at = NOW() WHERE id = $2",
+        hashed_password,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+    revoke_sessions(&data.redis, user.id).await?;
+
+    audit::record(
+        &data.db,
+        client,
+        Some(user.id),
+        "auth.password_changed",
+        Some(user.id),
+        json!({ "reason": reason }),
+    )
+    .await;
+    Ok(())
+}
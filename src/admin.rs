@@ -0,0 +1,353 @@
+use crate::{
+    audit,
+    client::ClientInfo,
+    model::User,
+    openapi, password_reset,
+    rbac::Role,
+    response::{ApiError, AppJson, GeneralResponse, Status},
+    schema::{AdminUserQuery, ChangeRoleSchema, SuspendUserSchema},
+    sessions::revoke_sessions,
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::Utc;
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+async fn find_user(data: &AppState, user_id: Uuid) -> Result<User, ApiError> {
+    sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?
+        .ok_or_else(|| ApiError::Fail("User not found".to_string()))
+}
+
+fn not_self(admin: &User, user_id: Uuid) -> Result<(), ApiError> {
+    if admin.id == user_id {
+        return Err(ApiError::Fail("admins can not do this to themselves".to_string()));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    summary = "Search users",
+    params(AdminUserQuery),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_get_users(
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<AdminUserQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let pattern = query.q.filter(|q| !q.is_empty()).map(|q| {
+        let escaped = q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let users: Vec<User> = sqlx::query_as!(
+        User,
+        "SELECT * FROM users
+        WHERE $1::TEXT IS NULL OR username ILIKE $1 OR email ILIKE $1
+        ORDER BY created_at DESC
+        LIMIT $2 OFFSET $3",
+        pattern,
+        limit,
+        offset
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "All users retrived".to_string(),
+        data: Some(json!(users)),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/admin/users/{user_id}/role",
+    tag = "admin",
+    summary = "Change a user's role",
+    request_body = ChangeRoleSchema,
+    params(("user_id" = Uuid, Path)),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_change_role(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    AppJson(body): AppJson<ChangeRoleSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    not_self(&admin, user_id)?;
+    let role = Role::from_name(&body.role)
+        .ok_or_else(|| ApiError::Fail("role must be user, moderator or admin".to_string()))?;
+    let user = find_user(&data, user_id).await?;
+
+    sqlx::query!(
+        "UPDATE users SET role = $1, updated_at = NOW() WHERE id = $2",
+        role.as_str(),
+        user_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.role_changed",
+        Some(user_id),
+        json!({ "from": user.role, "to": role.as_str() }),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Role changed".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/suspend",
+    tag = "admin",
+    summary = "Suspend a user",
+    request_body = SuspendUserSchema,
+    params(("user_id" = Uuid, Path)),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_suspend_user(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    AppJson(body): AppJson<SuspendUserSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    not_self(&admin, user_id)?;
+    let reason = body.reason.trim().to_string();
+    if reason.is_empty() {
+        return Err(ApiError::Fail("Missing field reason".to_string()));
+    }
+    if let Some(until) = body.until {
+        if until <= Utc::now() {
+            return Err(ApiError::Fail("until must be in the future".to_string()));
+        }
+    }
+    find_user(&data, user_id).await?;
+
+    sqlx::query!(
+        "UPDATE users SET suspended_at = NOW(), suspended_until = $1, suspension_reason = $2, updated_at = NOW() WHERE id = $3",
+        body.until,
+        reason,
+        user_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let revoked = revoke_sessions(&data.redis, user_id).await?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.user_suspended",
+        Some(user_id),
+        json!({ "reason": reason, "until": body.until, "sessions_revoked": revoked }),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "User suspended".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/unsuspend",
+    tag = "admin",
+    summary = "Lift a suspension",
+    params(("user_id" = Uuid, Path)),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_unsuspend_user(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    find_user(&data, user_id).await?;
+
+    sqlx::query!(
+        "UPDATE users SET suspended_at = NULL, suspended_until = NULL, suspension_reason = NULL, updated_at = NOW() WHERE id = $1",
+        user_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.user_unsuspended",
+        Some(user_id),
+        json!({}),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "User unsuspended".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/logout",
+    tag = "admin",
+    summary = "End every session of a user",
+    params(("user_id" = Uuid, Path)),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_logout_user(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    find_user(&data, user_id).await?;
+    let revoked = revoke_sessions(&data.redis, user_id).await?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.sessions_revoked",
+        Some(user_id),
+        json!({ "sessions_revoked": revoked }),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "User logged out".to_string(),
+        data: Some(json!({ "sessions_revoked": revoked })),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/password_reset",
+    tag = "admin",
+    summary = "Lock the password and email the user a reset link",
+    params(("user_id" = Uuid, Path)),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_force_password_reset(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = find_user(&data, user_id).await?;
+
+    sqlx::query!(
+        "UPDATE users SET password_reset_required = TRUE, updated_at = NOW() WHERE id = $1",
+        user_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let revoked = revoke_sessions(&data.redis, user_id).await?;
+    password_reset::send_reset_link(&data, &user).await?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.password_reset_forced",
+        Some(user_id),
+        json!({ "sessions_revoked": revoked }),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Password locked, a reset link was emailed to the user".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{user_id}",
+    tag = "admin",
+    summary = "Delete a user",
+    params(("user_id" = Uuid, Path)),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_delete_user(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    not_self(&admin, user_id)?;
+    let user = find_user(&data, user_id).await?;
+    revoke_sessions(&data.redis, user_id).await?;
+
+    // profiles, posts, reactions and tokens go with it through ON DELETE CASCADE
+    sqlx::query!("DELETE FROM users WHERE id = $1", user_id)
+        .execute(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.user_deleted",
+        Some(user_id),
+        json!({ "username": user.username, "email": user.email }),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "User deleted".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
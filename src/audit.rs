@@ -0,0 +1,101 @@
+use crate::{
+    client::ClientInfo,
+    model::AuditEvent,
+    openapi,
+    response::{ApiError, GeneralResponse, Status},
+    schema::AuditQuery,
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// audit_events is append-only, a trigger rejects updates and deletes
+pub async fn insert(
+    db: &Pool<Postgres>,
+    client: &ClientInfo,
+    actor_id: Option<Uuid>,
+    action: &str,
+    target_id: Option<Uuid>,
+    metadata: Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO audit_events (actor_id, action, target_id, ip, user_agent, metadata) VALUES ($1, $2, $3, $4, $5, $6)",
+        actor_id,
+        action,
+        target_id,
+        client.ip,
+        client.user_agent,
+        metadata
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+// the action being audited has already happened by the time this runs, a
+// failed insert is logged instead of turning a done delete or login into a 500
+pub async fn record(
+    db: &Pool<Postgres>,
+    client: &ClientInfo,
+    actor_id: Option<Uuid>,
+    action: &str,
+    target_id: Option<Uuid>,
+    metadata: Value,
+) {
+    if let Err(err) = insert(db, client, actor_id, action, target_id, metadata).await {
+        tracing::error!(action, ?target_id, error = %err, "failed to record audit event");
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    summary = "Search the audit log",
+    params(AuditQuery),
+    security(("session" = ["audit:read"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_get_audit_events(
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let events: Vec<AuditEvent> = sqlx::query_as!(
+        AuditEvent,
+        "SELECT id, actor_id, action, target_id, ip, user_agent, metadata, created_at FROM audit_events
+        WHERE ($1::UUID IS NULL OR actor_id = $1)
+        AND ($2::TEXT IS NULL OR action = $2)
+        AND ($3::UUID IS NULL OR target_id = $3)
+        AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+        AND ($5::TIMESTAMPTZ IS NULL OR created_at < $5)
+        ORDER BY created_at DESC
+        LIMIT $6 OFFSET $7",
+        query.actor_id,
+        query.action,
+        query.target_id,
+        query.since,
+        query.until,
+        limit,
+        offset
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Audit events retrived".to_string(),
+        data: Some(json!(events)),
+    };
+    Ok(Json(response))
+}
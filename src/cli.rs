@@ -0,0 +1,280 @@
+use crate::{
+    accounts, audit,
+    client::ClientInfo,
+    config::{Config, LogFormat, RegistrationMode},
+    connect_db, load_breached_passwords, load_config,
+    rbac::Role,
+    response::ApiError,
+    schema::RegisterUserSchema,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use clap::{Parser, Subcommand};
+use serde_json::json;
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    PgPool,
+};
+use std::{collections::HashSet, path::PathBuf};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Parser)]
+#[command(name = "server", about = "The API server and its maintenance commands")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the HTTP server, this is the default
+    Serve {
+        /// Apply pending migrations before serving, same as AUTO_MIGRATE=true
+        #[arg(long)]
+        migrate: bool,
+    },
+    /// Apply, revert or list the embedded database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+    /// Create an admin account, a password is generated when none is given
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Load users, profiles, posts and reactions from a directory of JSON or
+    /// YAML fixtures, or generate synthetic data with --users and --posts
+    Seed {
+        #[arg(default_value = "test")]
+        dir: PathBuf,
+        /// Accept fixture passwords that fail the password policy
+        #[arg(long)]
+        allow_weak_passwords: bool,
+        /// Generate this many synthetic users instead of reading fixtures
+        #[arg(long, requires = "posts")]
+        users: Option<usize>,
+        /// Generate this many synthetic posts, spread unevenly over the users
+        #[arg(long, requires = "users")]
+        posts: Option<usize>,
+    },
+    /// Replay requests recorded with RECORD_REQUESTS=true against a running server
+    /// and report responses whose status or body shape changed
+    Replay {
+        #[arg(default_value = "requests.jsonl")]
+        file: PathBuf,
+        /// Base url of the server to replay against
+        #[arg(long, default_value = "http://127.0.0.1:8000")]
+        target: String,
+        /// Sent in place of the redacted password fields
+        #[arg(long)]
+        password: Option<String>,
+        /// Bearer token sent where the recording had an Authorization header
+        #[arg(long)]
+        token: Option<String>,
+        /// Replay the file on this many clients at once, each with its own session
+        #[arg(long, default_value_t = 1)]
+        clients: usize,
+    },
+    /// Validate the configuration and print what would be used
+    CheckConfig,
+    /// Suggest argon2 parameters for this host, run it with --release
+    BenchArgon2 {
+        /// Milliseconds a single hash may take
+        #[arg(default_value_t = 500)]
+        target_ms: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateAction {
+    /// Apply every pending migration
+    Up,
+    /// Revert the most recent migrations
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List migrations and whether they are applied
+    Status,
+}
+
+pub fn fail(message: String) -> ! {
+    println!("🔥 {}", message);
+    std::process::exit(1);
+}
+
+pub fn describe(err: ApiError) -> String {
+    match err {
+        ApiError::Fail(message) => message,
+        ApiError::InternalServerError => "database error".to_string(),
+        ApiError::JsonRejection(rejection) => rejection.body_text(),
+    }
+}
+
+pub async fn migrate(action: MigrateAction) {
+    let config = load_config();
+    let pool = connect_db(&config).await;
+    match action {
+        MigrateAction::Up => {
+            if let Err(err) = MIGRATOR.run(&pool).await {
+                fail(format!("Failed to run migrations: {}", err));
+            }
+            println!("✅ Migrations are up to date");
+        }
+        MigrateAction::Down { steps } => {
+            let mut versions = applied_versions(&pool).await;
+            versions.sort_unstable();
+            // undo reverts everything newer than the target version
+            let target = match versions.len().checked_sub(steps + 1) {
+                Some(index) => versions[index],
+                None => 0,
+            };
+            if let Err(err) = MIGRATOR.undo(&pool, target).await {
+                fail(format!("Failed to revert migrations: {}", err));
+            }
+            println!("✅ Reverted {} migration(s)", steps.min(versions.len()));
+        }
+        MigrateAction::Status => {
+            let applied: HashSet<i64> = applied_versions(&pool).await.into_iter().collect();
+            for migration in MIGRATOR.iter().filter(|m| m.migration_type.is_up_migration()) {
+                let state = if applied.contains(&migration.version) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!("{} {:<8} {}", migration.version, state, migration.description);
+            }
+        }
+    }
+}
+
+async fn applied_versions(pool: &PgPool) -> Vec<i64> {
+    let result = async {
+        let mut conn = pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        conn.list_applied_migrations().await
+    }
+    .await;
+    match result {
+        Ok(applied) => applied.into_iter().map(|migration| migration.version).collect(),
+        Err(err) => fail(format!("Failed to read applied migrations: {}", err)),
+    }
+}
+
+pub async fn create_admin(username: String, email: String, password: Option<String>) {
+    let config = load_config();
+    let pool = connect_db(&config).await;
+    let breached = load_breached_passwords(&config);
+
+    let generated = password.is_none();
+    let password = password.unwrap_or_else(|| {
+        let mut bytes = [0u8; 18];
+        OsRng.fill_bytes(&mut bytes);
+        URL_SAFE_NO_PAD.encode(bytes)
+    });
+    let account = RegisterUserSchema {
+        username,
+        email,
+        password,
+    };
+    let user_id = match accounts::create_account(&pool, &config, &breached, &account, Role::Admin, true).await {
+        Ok(user_id) => user_id,
+        Err(err) => fail(format!("Failed to create admin: {}", describe(err))),
+    };
+    if audit::insert(
+        &pool,
+        &ClientInfo::default(),
+        None,
+        "admin.created_from_cli",
+        Some(user_id),
+        json!({ "username": account.username }),
+    )
+    .await
+    .is_err()
+    {
+        println!("⚠️ Admin created but the audit event was not recorded");
+    }
+
+    println!("✅ Admin {} created", account.username);
+    if generated {
+        println!("Password: {}", account.password);
+    }
+}
+
+// secrets are never printed, only whether they are set
+pub fn check_config() {
+    let config = match Config::init() {
+        Ok(config) => config,
+        Err(err) => fail(format!("Invalid configuration: {}", err)),
+    };
+    let redact = |url: &str| match url.split_once("://").and_then(|(scheme, rest)| {
+        rest.rsplit_once('@').map(|(_, host)| format!("{}://***@{}", scheme, host))
+    }) {
+        Some(redacted) => redacted,
+        None => url.to_string(),
+    };
+    let set = |value: &Option<String>| if value.is_some() { "set" } else { "not set" };
+    let registration_mode = match config.registration_mode {
+        RegistrationMode::Open => "open",
+        RegistrationMode::InviteOnly => "invite_only",
+        RegistrationMode::Closed => "closed",
+    };
+
+    println!("✅ Configuration is valid");
+    println!("bind_address       {}", config.bind_address);
+    println!("log_level          {}", config.log_filter);
+    println!(
+        "log_format         {}",
+        match config.log_format {
+            LogFormat::Pretty => "pretty",
+            LogFormat::Json => "json",
+        }
+    );
+    println!("database_url       {}", redact(&config.database_url));
+    println!("database_pool      {}", config.database_max_connections);
+    println!("redis_url          {}", redact(&config.redis_url));
+    println!("redis_pool         {}", config.redis_pool_size);
+    println!("auto_migrate       {}", config.auto_migrate);
+    println!("public_url         {}", config.public_url);
+    println!("cors_origins       {}", config.cors_origins.join(", "));
+    println!("registration_mode  {}", registration_mode);
+    println!("mailer             {}", config.mailer);
+    println!(
+        "oidc_providers     {}",
+        config
+            .oidc_providers
+            .iter()
+            .map(|provider| provider.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+    println!("password_pepper    {}", set(&config.password_pepper));
+    println!(
+        "breached_passwords {}",
+        config.breached_passwords_dir.as_deref().unwrap_or("not set")
+    );
+    println!("metrics_token      {}", set(&config.metrics_token));
+    println!(
+        "otlp_endpoint      {}",
+        config.otlp_endpoint.as_deref().unwrap_or("not set")
+    );
+    println!("api_docs           {}", config.api_docs);
+    if config.legacy_routes {
+        println!(
+            "legacy_routes      sunset {}",
+            config
+                .legacy_routes_sunset
+                .map(|date| date.to_string())
+                .unwrap_or_else(|| "not set".to_string())
+        );
+    }
+    if config.record_requests {
+        println!("record_requests    {}", config.record_file);
+    }
+}
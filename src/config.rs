@@ -1,22 +1,461 @@
+use std::{net::SocketAddr, path::Path, str::FromStr};
+
+#[derive(Debug, Clone)]
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegistrationMode {
+    Open,
+    InviteOnly,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
 #[derive(Debug,Clone)]
 pub struct Config {
     pub database_url: String,
+    pub database_max_connections: u32,
+    pub auto_migrate: bool,
+    pub redis_url: String,
+    pub redis_pool_size: usize,
+    pub bind_address: SocketAddr,
+    pub log_filter: String,
+    pub log_format: LogFormat,
+    pub trust_proxy: bool,
+    pub cors_origins: Vec<String>,
+    pub hsts_max_age: u64,
+    pub content_security_policy: Option<String>,
+    pub asset_content_security_policy: Option<String>,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+    pub app_secret: String,
+    pub public_url: String,
+    pub login_redirect: String,
+    pub password_reset_url: String,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    pub mailer: String,
+    pub mail_from: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub account_grace_days: i32,
+    pub export_dir: String,
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub password_pepper: Option<String>,
+    pub password_min_length: usize,
+    pub password_min_score: u8,
+    pub breached_passwords_dir: Option<String>,
+    pub registration_mode: RegistrationMode,
+    pub registration_domains: Vec<String>,
+    pub pow_on_register: bool,
+    pub pow_on_posts: bool,
+    pub pow_base_difficulty: u32,
+    pub pow_max_difficulty: u32,
+    pub pow_volume_threshold: i64,
+    pub record_requests: bool,
+    pub record_file: String,
+    pub shutdown_delay_secs: u64,
+    pub shutdown_timeout_secs: u64,
+    pub metrics_token: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub otel_service_name: String,
+    pub otel_sample_ratio: f64,
+    pub api_docs: bool,
+    pub legacy_routes: bool,
+    pub legacy_routes_sunset: Option<chrono::NaiveDate>,
     //pub jwt_secret: String,
     //pub jwt_expires_in: String,
     //pub jwt_maxage: i32,
 }
 
+// every setting is read from the environment first and then from the
+// optional TOML file, where keys are the variable names in lowercase:
+// BIND_ADDRESS=0.0.0.0:8000 in the env is bind_address = "0.0.0.0:8000" in the file
+struct Settings {
+    file: toml::Table,
+}
+
+impl Settings {
+    fn load() -> Result<Settings, String> {
+        let (path, explicit) = match std::env::var("CONFIG_FILE") {
+            Ok(path) => (path, true),
+            Err(_) => ("config.toml".to_string(), false),
+        };
+        if !explicit && !Path::new(&path).exists() {
+            return Ok(Settings { file: toml::Table::new() });
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| format!("can not read config file {}: {}", path, err))?;
+        let file = contents
+            .parse::<toml::Table>()
+            .map_err(|err| format!("config file {} is not valid TOML: {}", path, err))?;
+        Ok(Settings { file })
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        if let Ok(value) = std::env::var(name) {
+            return Some(value);
+        }
+        match self.file.get(&name.to_ascii_lowercase())? {
+            toml::Value::String(value) => Some(value.clone()),
+            toml::Value::Array(values) => Some(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        toml::Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            value => Some(value.to_string()),
+        }
+    }
+
+    fn optional(&self, name: &str) -> Option<String> {
+        self.get(name).filter(|value| !value.is_empty())
+    }
+
+    fn required(&self, name: &str) -> Result<String, String> {
+        self.optional(name).ok_or_else(|| format!("{} must be set", name))
+    }
+
+    fn string(&self, name: &str, default: &str) -> String {
+        self.optional(name).unwrap_or_else(|| default.to_string())
+    }
+
+    fn parse<T: FromStr>(&self, name: &str, default: T, expected: &str) -> Result<T, String> {
+        match self.optional(name) {
+            Some(value) => value
+                .trim()
+                .parse::<T>()
+                .map_err(|_| format!("{} must be {}, got {:?}", name, expected, value)),
+            None => Ok(default),
+        }
+    }
+
+    fn flag(&self, name: &str, default: bool) -> Result<bool, String> {
+        match self.optional(name).as_deref().map(str::trim) {
+            Some("true") | Some("1") => Ok(true),
+            Some("false") | Some("0") => Ok(false),
+            Some(value) => Err(format!("{} must be true or false, got {:?}", name, value)),
+            None => Ok(default),
+        }
+    }
+
+    // a response header value, set to an empty string to leave the header out
+    fn header(&self, name: &str, default: &str) -> Result<Option<String>, String> {
+        let value = self.get(name).unwrap_or_else(|| default.to_string());
+        if value.is_empty() {
+            return Ok(None);
+        }
+        axum::http::HeaderValue::from_str(&value)
+            .map_err(|_| format!("{} must be a valid header value, got {:?}", name, value))?;
+        Ok(Some(value))
+    }
+
+    // comma separated in the env, a list or a comma separated string in the file
+    fn list(&self, name: &str, default: &str) -> Vec<String> {
+        self.string(name, default)
+            .split(',')
+            .map(|item| item.trim().to_ascii_lowercase())
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+}
+
 impl Config {
-    pub fn init() -> Config {
-        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    // fails with a message naming the setting that is missing or invalid
+    pub fn init() -> Result<Config, String> {
+        let settings = Settings::load()?;
+
+        let database_url = settings.required("DATABASE_URL")?;
+        let database_max_connections =
+            settings.parse("DATABASE_MAX_CONNECTIONS", 10u32, "a positive number")?;
+        if database_max_connections == 0 {
+            return Err("DATABASE_MAX_CONNECTIONS must be at least 1".to_string());
+        }
+        // apply the embedded migrations when the server starts
+        let auto_migrate = settings.flag("AUTO_MIGRATE", false)?;
+        let redis_url = settings.string("REDIS_URL", "redis://127.0.0.1:6379");
+        tower_sessions_redis_store::fred::types::RedisConfig::from_url(&redis_url)
+            .map_err(|err| format!("REDIS_URL is not a valid redis url: {}", err))?;
+        let redis_pool_size = settings.parse("REDIS_POOL_SIZE", 6usize, "a positive number")?;
+        if redis_pool_size == 0 {
+            return Err("REDIS_POOL_SIZE must be at least 1".to_string());
+        }
+        let bind_address = settings.parse(
+            "BIND_ADDRESS",
+            SocketAddr::from(([0, 0, 0, 0], 8000)),
+            "an address like 0.0.0.0:8000",
+        )?;
+        // EnvFilter directives, a plain level or per target like info,sqlx=warn
+        let log_filter = settings.string("LOG_LEVEL", "info");
+        tracing_subscriber::EnvFilter::try_new(&log_filter).map_err(|err| {
+            format!(
+                "LOG_LEVEL must be a level or filter like info,sqlx=warn, got {:?}: {}",
+                log_filter, err
+            )
+        })?;
+        let log_format = match settings.string("LOG_FORMAT", "pretty").as_str() {
+            "pretty" => LogFormat::Pretty,
+            "json" => LogFormat::Json,
+            format => return Err(format!("LOG_FORMAT must be pretty or json, got {:?}", format)),
+        };
+        // only trust X-Forwarded-For when running behind our own proxy
+        let trust_proxy = settings.flag("TRUST_PROXY", false)?;
+        // CORS_ORIGINS=https://app.example.com,https://admin.example.com, also trusted by the CSRF check
+        let cors_origins: Vec<String> = settings
+            .list("CORS_ORIGINS", "http://localhost:5173")
+            .into_iter()
+            .map(|origin| origin.trim_end_matches('/').to_string())
+            .collect();
+        for origin in &cors_origins {
+            if !(origin.starts_with("http://") || origin.starts_with("https://"))
+                || axum::http::HeaderValue::from_str(origin).is_err()
+            {
+                return Err(format!(
+                    "CORS_ORIGINS entries must look like https://app.example.com, got {:?}",
+                    origin
+                ));
+            }
+        }
+        // security headers, an empty value turns one off
+        let hsts_max_age = settings.parse("HSTS_MAX_AGE", 31536000u64, "a number of seconds")?;
+        let content_security_policy = settings.header(
+            "CONTENT_SECURITY_POLICY",
+            "default-src 'none'; frame-ancestors 'none'",
+        )?;
+        let asset_content_security_policy = settings.header(
+            "ASSET_CONTENT_SECURITY_POLICY",
+            "default-src 'none'; img-src 'self'; style-src 'unsafe-inline'; sandbox",
+        )?;
+        let frame_options = settings.header("FRAME_OPTIONS", "DENY")?;
+        let referrer_policy = settings.header("REFERRER_POLICY", "no-referrer")?;
+        let permissions_policy = settings.header(
+            "PERMISSIONS_POLICY",
+            "camera=(), microphone=(), geolocation=(), payment=()",
+        )?;
+        // OIDC_PROVIDERS=local,google then OIDC_LOCAL_ISSUER, OIDC_LOCAL_CLIENT_ID, ...
+        let oidc_providers = settings
+            .list("OIDC_PROVIDERS", "")
+            .iter()
+            .map(|name| OidcProviderConfig::from_settings(&settings, name))
+            .collect::<Result<Vec<OidcProviderConfig>, String>>()?;
+        // signs magic links and anything else we hand out and verify later
+        let app_secret = settings.required("APP_SECRET")?;
+        let public_url = settings
+            .string("PUBLIC_URL", "http://localhost:8000")
+            .trim_end_matches('/')
+            .to_string();
+        // where the browser lands after an oidc or magic link login
+        let login_redirect = settings.string("LOGIN_REDIRECT", "http://localhost:5173");
+        // the frontend page a reset link opens, it gets token, expires and sig in the query
+        let password_reset_url =
+            settings.string("PASSWORD_RESET_URL", "http://localhost:5173/reset-password");
+        if reqwest::Url::parse(&password_reset_url).is_err() {
+            return Err(format!("PASSWORD_RESET_URL is not a url, got {:?}", password_reset_url));
+        }
+        let mailer = settings.string("MAILER", "log");
+        if mailer != "log" && mailer != "smtp" {
+            return Err(format!("MAILER must be log or smtp, got {:?}", mailer));
+        }
+        let mail_from = settings.string("MAIL_FROM", "Server <noreply@localhost>");
+        mail_from
+            .parse::<lettre::message::Mailbox>()
+            .map_err(|_| format!("MAIL_FROM must be a mailbox like \"Server <noreply@example.com>\", got {:?}", mail_from))?;
+        let smtp_host = settings.string("SMTP_HOST", "localhost");
+        let smtp_port = settings.parse("SMTP_PORT", 587u16, "a port number")?;
+        let smtp_username = settings.optional("SMTP_USERNAME");
+        let smtp_password = settings.optional("SMTP_PASSWORD");
+        // days a deactivated account can still be restored by logging in
+        let account_grace_days = settings.parse("ACCOUNT_GRACE_DAYS", 30i32, "a number of days")?;
+        let export_dir = settings.string("EXPORT_DIR", "./exports");
+        // defaults are the argon2 crate defaults, see `cargo run --release -- bench-argon2`
+        let argon2_memory_kib =
+            settings.parse("ARGON2_MEMORY_KIB", argon2::Params::DEFAULT_M_COST, "a number")?;
+        let argon2_iterations =
+            settings.parse("ARGON2_ITERATIONS", argon2::Params::DEFAULT_T_COST, "a number")?;
+        let argon2_parallelism =
+            settings.parse("ARGON2_PARALLELISM", argon2::Params::DEFAULT_P_COST, "a number")?;
+        argon2::Params::new(argon2_memory_kib, argon2_iterations, argon2_parallelism, None)
+            .map_err(|err| {
+                format!(
+                    "ARGON2_MEMORY_KIB, ARGON2_ITERATIONS and ARGON2_PARALLELISM are not valid argon2 parameters: {}",
+                    err
+                )
+            })?;
+        // kept out of the database, changing it locks out every peppered password
+        let password_pepper = settings.optional("PASSWORD_PEPPER");
+        let password_min_length = settings.parse("PASSWORD_MIN_LENGTH", 8usize, "a number")?;
+        // zxcvbn score from 0 (guessable) to 4 (very strong)
+        let password_min_score = settings.parse("PASSWORD_MIN_SCORE", 3u8, "a number")?;
+        if password_min_score > 4 {
+            return Err("PASSWORD_MIN_SCORE must be between 0 and 4".to_string());
+        }
+        // a directory of Pwned Passwords range files, see password::BreachedPasswords
+        let breached_passwords_dir = settings.optional("BREACHED_PASSWORDS_DIR");
+        let registration_mode = match settings.string("REGISTRATION_MODE", "open").as_str() {
+            "open" => RegistrationMode::Open,
+            "invite_only" => RegistrationMode::InviteOnly,
+            "closed" => RegistrationMode::Closed,
+            mode => {
+                return Err(format!(
+                    "REGISTRATION_MODE must be open, invite_only or closed, got {:?}",
+                    mode
+                ))
+            }
+        };
+        // REGISTRATION_DOMAINS=example.com,example.org, empty allows every domain
+        let registration_domains = settings.list("REGISTRATION_DOMAINS", "");
+        let pow_on_register = settings.flag("POW_ON_REGISTER", false)?;
+        let pow_on_posts = settings.flag("POW_ON_POSTS", false)?;
+        // leading zero bits, 16 takes a browser well under a second
+        let pow_base_difficulty = settings.parse("POW_BASE_DIFFICULTY", 16u32, "a number")?;
+        let pow_max_difficulty = settings.parse("POW_MAX_DIFFICULTY", 24u32, "a number")?;
+        if pow_base_difficulty > pow_max_difficulty || pow_max_difficulty > 64 {
+            return Err(
+                "POW_BASE_DIFFICULTY must not exceed POW_MAX_DIFFICULTY, which must be at most 64"
+                    .to_string(),
+            );
+        }
+        // solved challenges per minute before the difficulty starts to climb
+        let pow_volume_threshold = settings
+            .parse("POW_VOLUME_THRESHOLD", 30i64, "a number")?
+            .max(1);
+        // append every api request and response to RECORD_FILE for `server replay`,
+        // a debugging aid that should stay off in production
+        let record_requests = settings.flag("RECORD_REQUESTS", false)?;
+        let record_file = settings.string("RECORD_FILE", "requests.jsonl");
+        // on SIGTERM /readyz fails for the delay so load balancers stop sending
+        // traffic, then in-flight requests get up to the timeout to finish
+        let shutdown_delay_secs = settings.parse("SHUTDOWN_DELAY_SECS", 0u64, "a number of seconds")?;
+        let shutdown_timeout_secs =
+            settings.parse("SHUTDOWN_TIMEOUT_SECS", 30u64, "a number of seconds")?;
+        // /openapi.json is always served, this adds a Redoc page for it at /docs
+        let api_docs = settings.flag("API_DOCS", false)?;
+        // the api paths without the /v1 prefix, answered with Deprecation headers
+        // and, once a date is set, a Sunset header
+        let legacy_routes = settings.flag("LEGACY_ROUTES", true)?;
+        let legacy_routes_sunset = match settings.optional("LEGACY_ROUTES_SUNSET") {
+            Some(date) => Some(
+                chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").map_err(|_| {
+                    format!("LEGACY_ROUTES_SUNSET must be a date like 2027-06-30, got {:?}", date)
+                })?,
+            ),
+            None => None,
+        };
+        // when set, scrapers send it as a bearer token to read /metrics
+        let metrics_token = settings.optional("METRICS_TOKEN");
+        // OTLP over http, like http://localhost:4318, spans are only exported when it is set
+        let otlp_endpoint = settings
+            .optional("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .map(|endpoint| endpoint.trim_end_matches('/').to_string());
+        if let Some(endpoint) = &otlp_endpoint {
+            if endpoint.parse::<axum::http::Uri>().is_err() {
+                return Err(format!(
+                    "OTEL_EXPORTER_OTLP_ENDPOINT must be a url like http://localhost:4318, got {:?}",
+                    endpoint
+                ));
+            }
+        }
+        let otel_service_name = settings.string("OTEL_SERVICE_NAME", "server");
+        // share of new traces to keep, a sampled traceparent from the caller always wins
+        let otel_sample_ratio = settings.parse("OTEL_SAMPLE_RATIO", 1.0f64, "a number")?;
+        if !(0.0..=1.0).contains(&otel_sample_ratio) {
+            return Err("OTEL_SAMPLE_RATIO must be between 0 and 1".to_string());
+        }
         //let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
         //let jwt_expires_in = std::env::var("JWT_EXPIRED_IN").expect("JWT_EXPIRED_IN must be set");
         //let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
-        Config {
+        Ok(Config {
             database_url,
+            database_max_connections,
+            auto_migrate,
+            redis_url,
+            redis_pool_size,
+            bind_address,
+            log_filter,
+            log_format,
+            trust_proxy,
+            cors_origins,
+            hsts_max_age,
+            content_security_policy,
+            asset_content_security_policy,
+            frame_options,
+            referrer_policy,
+            permissions_policy,
+            app_secret,
+            public_url,
+            login_redirect,
+            password_reset_url,
+            oidc_providers,
+            mailer,
+            mail_from,
+            smtp_host,
+            smtp_port,
+            smtp_username,
+            smtp_password,
+            account_grace_days,
+            export_dir,
+            argon2_memory_kib,
+            argon2_iterations,
+            argon2_parallelism,
+            password_pepper,
+            password_min_length,
+            password_min_score,
+            breached_passwords_dir,
+            registration_mode,
+            registration_domains,
+            pow_on_register,
+            pow_on_posts,
+            pow_base_difficulty,
+            pow_max_difficulty,
+            pow_volume_threshold,
+            record_requests,
+            record_file,
+            shutdown_delay_secs,
+            shutdown_timeout_secs,
+            metrics_token,
+            otlp_endpoint,
+            otel_service_name,
+            otel_sample_ratio,
+            api_docs,
+            legacy_routes,
+            legacy_routes_sunset,
             //jwt_secret,
             //jwt_expires_in,
             //sjwt_maxage: jwt_maxage.parse::<i32>().unwrap(),
-        }
+        })
+    }
+}
+
+impl OidcProviderConfig {
+    fn from_settings(settings: &Settings, name: &str) -> Result<OidcProviderConfig, String> {
+        let key = |field: &str| format!("OIDC_{}_{}", name.to_ascii_uppercase(), field);
+        Ok(OidcProviderConfig {
+            name: name.to_string(),
+            issuer: settings.required(&key("ISSUER"))?.trim_end_matches('/').to_string(),
+            client_id: settings.required(&key("CLIENT_ID"))?,
+            client_secret: settings.optional(&key("CLIENT_SECRET")),
+            redirect_url: settings.required(&key("REDIRECT_URL"))?,
+            scopes: settings.string(&key("SCOPES"), "openid email profile"),
+        })
     }
 }
@@ -0,0 +1,136 @@
+use crate::{
+    openapi,
+    response::{ApiError, GeneralResponse, Status},
+    AppState,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Method, Request},
+    middleware::Next,
+    response::IntoResponse,
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde_json::json;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+pub const CSRF_HEADER: &str = "x-csrf-token";
+const SESSION_KEY: &str = "csrf_token";
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// "https://app.example.com/some/page" -> "https://app.example.com"
+fn origin_of(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    Some(format!("{}://{}", scheme, host).to_ascii_lowercase())
+}
+
+fn origin_allowed(data: &AppState, origin: &str) -> bool {
+    let origin = origin.trim_end_matches('/').to_ascii_lowercase();
+    data.env.cors_origins.contains(&origin)
+        || origin_of(&data.env.public_url).as_deref() == Some(origin.as_str())
+}
+
+// a login gets a fresh token so one planted before it is worthless
+pub async fn rotate_token(session: &Session) -> Result<(), ApiError> {
+    session
+        .remove::<String>(SESSION_KEY)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    Ok(())
+}
+
+// GET /auth/csrf, the frontend sends the token back in X-CSRF-Token
+#[utoipa::path(
+    get,
+    path = "/auth/csrf",
+    tag = "auth",
+    summary = "The CSRF token for the session",
+    responses(openapi::Envelope)
+)]
+pub async fn get_csrf_token(session: Session) -> Result<impl IntoResponse, ApiError> {
+    let token = match session
+        .get::<String>(SESSION_KEY)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?
+    {
+        Some(token) => token,
+        None => {
+            let mut bytes = [0u8; 32];
+            OsRng.fill_bytes(&mut bytes);
+            let token = URL_SAFE_NO_PAD.encode(bytes);
+            session
+                .insert(SESSION_KEY, &token)
+                .await
+                .map_err(|_| ApiError::InternalServerError)?;
+            token
+        }
+    };
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "CSRF token".to_string(),
+        data: Some(json!({ "csrf_token": token, "header": CSRF_HEADER })),
+    };
+    Ok(Json(response))
+}
+
+// synchronizer token bound to the session plus an Origin/Referer check, for
+// every request that can change state. Bearer token requests carry no cookie
+// a browser could attach on its own, so they are left alone
+pub async fn csrf(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(next.run(req).await);
+    }
+    let is_bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Bearer "));
+    if is_bearer {
+        return Ok(next.run(req).await);
+    }
+
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| {
+            req.headers()
+                .get(header::REFERER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(origin_of)
+        });
+    if let Some(origin) = origin {
+        if !origin_allowed(&data, &origin) {
+            return Err(ApiError::Fail("request origin is not allowed".to_string()));
+        }
+    }
+
+    let expected = session
+        .get::<String>(SESSION_KEY)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    let provided = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match (expected, provided) {
+        (Some(expected), Some(provided)) if constant_time_eq(expected.as_bytes(), provided.as_bytes()) => {
+            Ok(next.run(req).await)
+        }
+        _ => Err(ApiError::Fail("missing or invalid CSRF token".to_string())),
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{route::API_PREFIX, AppState};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+pub const DEPRECATION_HEADER: &str = "deprecation";
+pub const SUNSET_HEADER: &str = "sunset";
+
+// 2026-10-18, when the api moved under /v1
+const DEPRECATED_SINCE: i64 = 1792281600;
+
+// the unprefixed paths still answer like /v1 does, the response says they are
+// deprecated (RFC 9745), when they go away (RFC 8594) and where to go instead
+pub async fn deprecated(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let successor = format!("<{}{}>; rel=\"successor-version\"", API_PREFIX, req.uri().path());
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static(DEPRECATION_HEADER),
+        HeaderValue::from_str(&format!("@{}", DEPRECATED_SINCE)).unwrap(),
+    );
+    if let Some(sunset) = data.env.legacy_routes_sunset {
+        let date = sunset.format("%a, %d %b %Y 00:00:00 GMT").to_string();
+        headers.insert(
+            HeaderName::from_static(SUNSET_HEADER),
+            HeaderValue::from_str(&date).unwrap(),
+        );
+    }
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.append(header::LINK, link);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, mailer::LogMailer, password::BreachedPasswords, route::create_router};
+    use axum::{
+        body::{to_bytes, Bytes},
+        http::{HeaderMap, StatusCode},
+        Router,
+    };
+    use chrono::NaiveDate;
+    use sqlx::postgres::PgPoolOptions;
+    use std::sync::atomic::AtomicBool;
+    use tower::ServiceExt;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+    use tower_sessions_redis_store::fred::prelude::*;
+
+    // the router as serve builds it, nothing here talks to Postgres or Redis
+    fn app(legacy_routes: bool, sunset: Option<NaiveDate>) -> Router {
+        std::env::set_var("DATABASE_URL", "postgresql://test@localhost:1/test");
+        std::env::set_var("APP_SECRET", "test");
+        let mut config = Config::init().unwrap();
+        config.legacy_routes = legacy_routes;
+        config.legacy_routes_sunset = sunset;
+        config.oidc_providers.clear();
+
+        let state = Arc::new(AppState {
+            db: PgPoolOptions::new().connect_lazy(&config.database_url).unwrap(),
+            redis: RedisPool::new(RedisConfig::default(), None, None, None, 1).unwrap(),
+            http: reqwest::Client::new(),
+            mailer: Arc::new(LogMailer),
+            breached_passwords: BreachedPasswords::default(),
+            recorder: None,
+            draining: AtomicBool::new(false),
+            env: config,
+        });
+        create_router(state).layer(SessionManagerLayer::new(MemoryStore::default()))
+    }
+
+    async fn get(app: &Router, path: &str) -> (StatusCode, HeaderMap, Bytes) {
+        let response = app
+            .clone()
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        (status, headers, to_bytes(response.into_body(), usize::MAX).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn legacy_paths_answer_like_v1_and_say_they_are_deprecated() {
+        let app = app(true, NaiveDate::from_ymd_opt(2027, 1, 1));
+
+        let (status, headers, body) = get(&app, "/v1/auth/oidc/providers").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers.get(DEPRECATION_HEADER).is_none());
+        assert!(headers.get(header::LINK).is_none());
+
+        let (legacy_status, legacy_headers, legacy_body) = get(&app, "/auth/oidc/providers").await;
+        assert_eq!(legacy_status, status);
+        assert_eq!(legacy_body, body);
+        // @ and the unix time of 2026-10-18T00:00:00Z
+        assert_eq!(legacy_headers[DEPRECATION_HEADER], "@1792281600");
+        assert_eq!(legacy_headers[SUNSET_HEADER], "Fri, 01 Jan 2027 00:00:00 GMT");
+        assert_eq!(
+            legacy_headers[header::LINK],
+            "</v1/auth/oidc/providers>; rel=\"successor-version\""
+        );
+    }
+
+    #[tokio::test]
+    async fn sunset_is_left_out_until_configured() {
+        let app = app(true, None);
+        let (status, headers, _) = get(&app, "/auth/oidc/providers").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers.get(DEPRECATION_HEADER).is_some());
+        assert!(headers.get(SUNSET_HEADER).is_none());
+    }
+
+    #[tokio::test]
+    async fn legacy_paths_can_be_turned_off() {
+        let app = app(false, None);
+        let (status, _, _) = get(&app, "/auth/oidc/providers").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _, _) = get(&app, "/v1/auth/oidc/providers").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
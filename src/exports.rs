@@ -0,0 +1,291 @@
+use crate::{
+    audit,
+    client::ClientInfo,
+    model::{DataExport, Profile, User},
+    openapi,
+    response::{ApiError, GeneralResponse, Status},
+    route::API_PREFIX,
+    schema::ExportDownloadQuery,
+    sessions::list_sessions,
+    signing,
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use std::{io::Write, path::PathBuf, sync::Arc};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+// how long a finished export can be downloaded before it is removed
+pub const EXPORT_TTL_DAYS: i64 = 7;
+
+fn download_payload(export_id: Uuid, expires: i64) -> String {
+    format!("export:{}:{}", export_id, expires)
+}
+
+pub fn download_url(data: &AppState, export_id: Uuid, expires_at: DateTime<Utc>) -> String {
+    let expires = expires_at.timestamp();
+    let sig = signing::sign(&data.env.app_secret, &download_payload(export_id, expires));
+    format!(
+        "{}{}/user/export/{}/download?expires={}&sig={}",
+        data.env.public_url, API_PREFIX, export_id, expires, sig
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/me/export",
+    tag = "exports",
+    summary = "Start a data export",
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn request_export(
+    Extension(user): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    // a partial unique index allows one pending or running export per user,
+    // two requests racing each other can not both get in
+    let export_id: Uuid = match sqlx::query_scalar!(
+        "INSERT INTO data_exports (user_id) VALUES ($1) RETURNING id",
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(export_id) => export_id,
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            return Err(ApiError::Fail("an export is already in progress".to_string()));
+        }
+        Err(_) => return Err(ApiError::InternalServerError),
+    };
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "account.export_requested",
+        Some(export_id),
+        json!({}),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Export queued, you will get an email when it is ready".to_string(),
+        data: Some(json!({ "id": export_id, "status": "pending" })),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/me/export",
+    tag = "exports",
+    summary = "The caller's data exports",
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn get_exports(
+    Extension(user): Extension<User>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let exports: Vec<DataExport> = sqlx::query_as!(
+        DataExport,
+        "SELECT * FROM data_exports WHERE user_id = $1 ORDER BY created_at DESC",
+        user.id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let exports: Vec<Value> = exports
+        .into_iter()
+        .map(|export| {
+            let download_url = match (export.status.as_str(), export.expires_at) {
+                ("ready", Some(expires_at)) if expires_at > Utc::now() => {
+                    Some(download_url(&data, export.id, expires_at))
+                }
+                _ => None,
+            };
+            json!({
+                "id": export.id,
+                "status": export.status,
+                "created_at": export.created_at,
+                "completed_at": export.completed_at,
+                "expires_at": export.expires_at,
+                "download_url": download_url,
+            })
+        })
+        .collect();
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "All exports retrived".to_string(),
+        data: Some(json!(exports)),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/export/{export_id}/download",
+    tag = "exports",
+    summary = "Download a finished export",
+    params(("export_id" = Uuid, Path), ExportDownloadQuery),
+    responses(openapi::Download)
+)]
+pub async fn download_export(
+    State(data): State<Arc<AppState>>,
+    Path(export_id): Path<Uuid>,
+    Query(query): Query<ExportDownloadQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !signing::verify(
+        &data.env.app_secret,
+        &download_payload(export_id, query.expires),
+        &query.sig,
+    ) {
+        return Err(ApiError::Fail("invalid download link".to_string()));
+    }
+    if query.expires < Utc::now().timestamp() {
+        return Err(ApiError::Fail("download link expired".to_string()));
+    }
+
+    let file_path: Option<String> = sqlx::query_scalar!(
+        "SELECT file_path FROM data_exports WHERE id = $1 AND status = 'ready' AND expires_at > NOW()",
+        export_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?
+    .flatten();
+    let file_path = file_path.ok_or_else(|| ApiError::Fail("export not found".to_string()))?;
+
+    let bytes = tokio::fs::read(&file_path)
+        .await
+        .map_err(|_| ApiError::Fail("export not found".to_string()))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"export-{}.zip\"", export_id),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+// collects everything we hold about the user and writes it to EXPORT_DIR
+pub async fn build_export(data: &AppState, export_id: Uuid, user_id: Uuid) -> Result<PathBuf, ApiError> {
+    let user = sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", user_id)
+        .fetch_one(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+    let profile: Option<Profile> = sqlx::query_as!(
+        Profile,
+        "SELECT * FROM profiles WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let posts: Vec<Value> = sqlx::query!(
+        "SELECT id, title, content, created_at, updated_at FROM posts WHERE author_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?
+    .into_iter()
+    .map(|post| {
+        json!({
+            "id": post.id,
+            "title": post.title,
+            "content": post.content,
+            "created_at": post.created_at,
+            "updated_at": post.updated_at,
+        })
+    })
+    .collect();
+
+    let reactions: Vec<Value> = sqlx::query!(
+        "SELECT post_id, is_like, created_at FROM post_reactions WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?
+    .into_iter()
+    .map(|reaction| {
+        json!({
+            "post_id": reaction.post_id,
+            "is_like": reaction.is_like,
+            "created_at": reaction.created_at,
+        })
+    })
+    .collect();
+
+    let sessions = list_sessions(&data.redis, user_id).await?;
+
+    // the default photo is shared by everyone and is not the user's media
+    let mut media: Vec<(String, Vec<u8>)> = Vec::new();
+    if let Some(profile) = &profile {
+        let photo = std::path::Path::new(&profile.photo);
+        if let Some(name) = photo.file_name().and_then(|name| name.to_str()) {
+            if !name.starts_with("default.") {
+                if let Ok(bytes) = tokio::fs::read(std::path::Path::new("./assets").join(name)).await {
+                    media.push((format!("media/{}", name), bytes));
+                }
+            }
+        }
+    }
+
+    let files = vec![
+        ("user.json", json!(user)),
+        ("profile.json", json!(profile)),
+        ("posts.json", json!(posts)),
+        ("reactions.json", json!(reactions)),
+        ("sessions.json", json!(sessions)),
+    ];
+
+    let archive = tokio::task::spawn_blocking(move || -> zip::result::ZipResult<Vec<u8>> {
+        let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        for (name, value) in files {
+            zip.start_file(name, options)?;
+            zip.write_all(&serde_json::to_vec_pretty(&value).unwrap_or_default())?;
+        }
+        for (name, bytes) in media {
+            zip.start_file(name, options)?;
+            zip.write_all(&bytes)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    })
+    .await
+    .map_err(|_| ApiError::InternalServerError)?
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    tokio::fs::create_dir_all(&data.env.export_dir)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    let path = PathBuf::from(&data.env.export_dir).join(format!("{}.zip", export_id));
+    tokio::fs::write(&path, archive)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    Ok(path)
+}
+
+pub fn expires_at() -> DateTime<Utc> {
+    Utc::now() + Duration::days(EXPORT_TTL_DAYS)
+}
@@ -1,65 +1,180 @@
 use crate::{
-    model::{Profile, Register, User},
+    accounts,
+    audit,
+    client::ClientInfo,
+    config::RegistrationMode,
+    filters::FilterdUser,
+    invites,
+    metrics,
+    model::{ApiToken, Profile, User},
+    openapi,
+    password,
+    pow,
+    rbac::{Permission, Permissions, Role},
     response::{ApiError, GeneralResponse, PostResponse, Status,AppJson},
-    schema::{CreatePostSchema, LikePostSchema, LoginUserSchema, RegisterUserSchema, RegisterUserSchemaOptional},
+    sessions::{end_session, revoke_sessions, start_session},
+    schema::{ChangePasswordSchema, CreateApiTokenSchema, DeleteAccountSchema, CreatePostSchema, LikePostSchema, LoginUserSchema, RegisterUserSchema, RegisterUserSchemaOptional},
+    tokens::{display_prefix, generate_token, hash_token},
     AppState,
 };
-use argon2::{
-    password_hash::{rand_core::OsRng, SaltString},
-    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
-};
 use axum::{
     extract::{Path, State},
     response::IntoResponse,
     Extension, Json,
 };
+use chrono::{Duration, Utc};
 use serde_json::json;
+use sqlx::PgPool;
 use std::sync::Arc;
 use tower_sessions::Session;
 use uuid::Uuid;
-use validator::Validate;
 
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    summary = "Start a session with a password",
+    request_body = LoginUserSchema,
+    responses(openapi::Envelope)
+)]
 pub async fn login_user_handler(
     session: Session,
+    client: ClientInfo,
     State(data): State<Arc<AppState>>,
     Json(body): Json<LoginUserSchema>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let user: Register = sqlx::query_as!(
-        Register,
-        "SELECT (id), (password) FROM users WHERE username = ($1)",
+    let user: Option<User> = sqlx::query_as!(
+        User,
+        "SELECT * FROM users WHERE username = ($1)",
         body.username
     )
     .fetch_optional(&data.db)
     .await
-    .map_err(|_| ApiError::InternalServerError)?
-    .ok_or_else(|| ApiError::Fail("user does not exist".to_string()))?;
+    .map_err(|_| ApiError::InternalServerError)?;
 
-    let is_valid = match PasswordHash::new(&user.password) {
-        Ok(parsed_hash) => Argon2::default()
-            .verify_password(body.password.as_bytes(), &parsed_hash)
-            .map_or(false, |_| true),
-        Err(_) => false,
+    let user = match user {
+        Some(user) => user,
+        None => {
+            audit::record(
+                &data.db,
+                &client,
+                None,
+                "auth.login_failed",
+                None,
+                json!({ "username": body.username, "reason": "unknown_user" }),
+            )
+            .await;
+            return Err(ApiError::Fail("user does not exist".to_string()));
+        }
     };
 
+    // accounts created through an identity provider may not have a password
+    let is_valid = user
+        .password
+        .as_deref()
+        .map(|hash| password::verify(&data.env, hash, &body.password))
+        .unwrap_or(false);
+
     if !is_valid {
+        audit::record(
+            &data.db,
+            &client,
+            Some(user.id),
+            "auth.login_failed",
+            Some(user.id),
+            json!({ "reason": "incorrect_password" }),
+        )
+        .await;
         return Err(ApiError::Fail("incorrect password".to_string()));
     }
 
-    session
-        .insert("user_id", user.id)
-        .await
-        .map_err(|_| ApiError::InternalServerError)?;
+    if user.is_suspended() {
+        audit::record(
+            &data.db,
+            &client,
+            Some(user.id),
+            "auth.login_failed",
+            Some(user.id),
+            json!({ "reason": "suspended" }),
+        )
+        .await;
+        return Err(ApiError::Fail("account is suspended".to_string()));
+    }
+
+    // the old password stays refused until the emailed reset link was used
+    if user.password_reset_required {
+        audit::record(
+            &data.db,
+            &client,
+            Some(user.id),
+            "auth.login_failed",
+            Some(user.id),
+            json!({ "reason": "password_reset_required" }),
+        )
+        .await;
+        return Err(ApiError::Fail(
+            "password reset required, use the link we emailed you".to_string(),
+        ));
+    }
+
+    if let Some(hash) = user.password.as_deref() {
+        // upgrade hashes made with older argon2 settings while we have the password
+        if password::needs_rehash(&data.env, hash) {
+            let hashed_password = password::hash(&data.env, &body.password)?;
+            sqlx::query!(
+                "UPDATE users SET password = $1 WHERE id = $2",
+                hashed_password,
+                user.id
+            )
+            .execute(&data.db)
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
+            tracing::info!("rehashed password of {}", user.id);
+        }
+    }
+
+    accounts::reactivate(&data, &client, &user).await?;
+    start_session(&session, &data.redis, &client, user.id).await?;
+    metrics::LOGINS.with_label_values(&["password"]).inc();
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "auth.login",
+        Some(user.id),
+        json!({ "method": "password" }),
+    )
+    .await;
 
     //WIERD FUNCTION
     let response = GeneralResponse::new(Status::Success, "user registerd", None);
     Ok(Json(response))
 }
 
-pub async fn logout_handler(session: Session) -> Result<impl IntoResponse, ApiError> {
-    session
-        .delete()
-        .await
-        .map_err(|_| ApiError::InternalServerError)?;
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    summary = "End the session",
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn logout_handler(
+    session: Session,
+    client: ClientInfo,
+    Extension(user): Extension<User>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    end_session(&session, &data.redis).await?;
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "auth.logout",
+        Some(user.id),
+        json!({}),
+    )
+    .await;
     let response: GeneralResponse = GeneralResponse {
         status: Status::Success,
         message: "User logged out".to_string(),
@@ -69,91 +184,93 @@ pub async fn logout_handler(session: Session) -> Result<impl IntoResponse, ApiEr
 }
 
 
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    summary = "Create an account",
+    request_body = RegisterUserSchemaOptional,
+    responses(openapi::Envelope)
+)]
 pub async fn register_user_handler(
+    client: ClientInfo,
     State(data): State<Arc<AppState>>,
     AppJson(body): AppJson<RegisterUserSchemaOptional>,
 ) -> Result<impl IntoResponse, ApiError> {
     let username = body.username.ok_or_else(|| ApiError::Fail("Missing field username".to_string()))?;
     let email = body.email.ok_or_else(|| ApiError::Fail("Missing field email".to_string()))?;
     let password = body.password.ok_or_else(|| ApiError::Fail("Missing field password".to_string()))?;
-    if username == "".to_string() {
+    let invite_code = body.invite_code.map(|code| code.trim().to_string());
+    if username.is_empty() {
         return Err(ApiError::Fail("Missing field username".to_string()));
     }
-    if email == "".to_string() {
+    if email.is_empty() {
         return Err(ApiError::Fail("Missing field email".to_string()));
     }
-    if password == "".to_string() {
+    if password.is_empty() {
         return Err(ApiError::Fail("Missing field password".to_string()));
     }
 
-    let body = RegisterUserSchema { username,email,password};
-    let user_exists: Option<bool> = sqlx::query_scalar!(
-        "SELECT EXISTS(SELECT 1 FROM users WHERE username = $1)",
-        body.username.to_owned()
-    )
-    .fetch_one(&data.db)
-    .await
-    .map_err(|_| ApiError::InternalServerError)?;
-
-    if let Some(exists) = user_exists {
-        if exists {
-            return Err(ApiError::Fail("username is taken".to_string()));
+    match data.env.registration_mode {
+        RegistrationMode::Open => {}
+        RegistrationMode::InviteOnly => {
+            if invite_code.as_deref().unwrap_or_default().is_empty() {
+                return Err(ApiError::Fail("an invite code is required".to_string()));
+            }
         }
-    }
-
-    let email_exists: Option<bool> = sqlx::query_scalar!(
-        "SELECT EXISTS(SELECT 1 FROM users WHERE email = $1)",
-        body.email
-    )
-    .fetch_one(&data.db)
-    .await
-    .map_err(|_| ApiError::InternalServerError)?;
-
-    if let Some(exists) = email_exists {
-        if exists {
-            return Err(ApiError::Fail("Email is taken".to_string()));
+        RegistrationMode::Closed => {
+            return Err(ApiError::Fail("registration is closed".to_string()));
         }
     }
+    if !invites::email_domain_allowed(&data.env, &email) {
+        return Err(ApiError::Fail("registration is not open for this email domain".to_string()));
+    }
 
-    if body.validate().is_err() {
-        return Err(ApiError::Fail("Email is not valid".to_string()));
+    let pow_solution = body.pow;
+    let body = RegisterUserSchema { username,email,password};
+    accounts::check_new_account(&data.db, &data.env, &data.breached_passwords, &body, true).await?;
+    // after the cheap checks, a sign up refused for a taken username keeps its
+    // solution, and before the expensive hash
+    if data.env.pow_on_register {
+        pow::verify(&data, "register", pow_solution.as_ref()).await?;
     }
-    let salt = SaltString::generate(&mut OsRng);
-    let hashed_password = Argon2::default()
-        .hash_password(body.password.as_bytes(), &salt)
-        .map_err(|_| ApiError::InternalServerError)
-        .map(|hash| hash.to_string())?;
+    let hashed_password = password::hash(&data.env, &body.password)?;
 
-    let tx = data
+    let mut tx = data
         .db
         .begin()
         .await
         .map_err(|_| ApiError::InternalServerError)?;
 
-    let user_id: Uuid = sqlx::query_scalar!(
-        "INSERT INTO users (username,email,password) VALUES ($1, $2, $3) RETURNING id",
-        body.username.to_string(),
-        body.email.to_string().to_ascii_lowercase(),
-        hashed_password
-    )
-    .fetch_one(&data.db)
-    .await
-    .map_err(|_| ApiError::InternalServerError)?;
+    let invite_id = match (data.env.registration_mode, &invite_code) {
+        (RegistrationMode::InviteOnly, Some(code)) => Some(invites::redeem(&mut tx, code).await?),
+        _ => None,
+    };
 
-    sqlx::query!("INSERT INTO profiles (user_id, photo, bio) VALUES ($1, $2, $3) RETURNING id, user_id,photo,bio,created_at,updated_at",
-        user_id,
-        "default.jpg".to_string(),
-        "".to_string(),
+    let user_id = accounts::insert_user(
+        &mut tx,
+        &body.username,
+        &body.email,
+        Some(&hashed_password),
+        Role::User,
     )
-    .fetch_one(&data.db)
-    .await.map_err(|_| {
-        ApiError::InternalServerError
-    })?;
+    .await?;
 
     tx.commit()
         .await
         .map_err(|_| ApiError::InternalServerError)?;
 
+    audit::record(
+        &data.db,
+        &client,
+        Some(user_id),
+        "auth.register",
+        Some(user_id),
+        json!({ "username": body.username, "invite_id": invite_id }),
+    )
+    .await;
+    metrics::REGISTRATIONS.with_label_values(&["password"]).inc();
+
     let response: GeneralResponse = GeneralResponse {
         status: Status::Success,
         message: "User registerd".to_string(),
@@ -162,20 +279,45 @@ pub async fn register_user_handler(
     Ok(Json(response))
 }
 
+// shared with the seeder so fixture posts are stored like real ones
+pub async fn insert_post(
+    db: &PgPool,
+    author_id: Uuid,
+    title: &str,
+    content: &str,
+) -> Result<Uuid, ApiError> {
+    sqlx::query_scalar!(
+        "INSERT INTO posts (author_id,title,content) VALUES ($1,$2,$3) RETURNING id",
+        author_id,
+        title,
+        content
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)
+}
+
+#[utoipa::path(
+    post,
+    path = "/post",
+    tag = "posts",
+    summary = "Create a post",
+    request_body = CreatePostSchema,
+    security(("session" = []), ("api_token" = ["posts:write"])),
+    responses(openapi::Envelope)
+)]
 pub async fn create_post(
     Extension(user): Extension<User>,
     State(data): State<Arc<AppState>>,
     Json(post): Json<CreatePostSchema>,
 ) -> Result<impl IntoResponse, ApiError> {
-    sqlx::query!(
-        "INSERT INTO posts (author_id,title,content) VALUES ($1,$2,$3)",
-        user.id,
-        post.title,
-        post.content
-    )
-    .execute(&data.db)
-    .await
-    .map_err(|_| ApiError::InternalServerError)?;
+    // api tokens pay the work too, any account can mint one at /user/tokens
+    if data.env.pow_on_posts {
+        pow::verify(&data, "post", post.pow.as_ref()).await?;
+    }
+
+    insert_post(&data.db, user.id, &post.title, &post.content).await?;
+    metrics::POSTS_CREATED.inc();
 
     let response: GeneralResponse = GeneralResponse {
         status: Status::Success,
@@ -186,6 +328,13 @@ pub async fn create_post(
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/post/get_all",
+    tag = "posts",
+    summary = "Every post with its reaction counts",
+    responses(openapi::Posts)
+)]
 pub async fn get_all_posts(
     State(data): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, ApiError> {
@@ -206,6 +355,7 @@ pub async fn get_all_posts(
         JOIN users ON posts.author_id = users.id
         JOIN profiles ON profiles.user_id = users.id
         LEFT JOIN post_reactions ON posts.id = post_reactions.post_id
+        WHERE users.deactivated_at IS NULL
         GROUP BY posts.id, users.username, posts.title, posts.content, posts.created_at, posts.updated_at, users.id, profiles.photo
         ORDER BY posts.created_at DESC"
     )
@@ -220,13 +370,24 @@ pub async fn get_all_posts(
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/user/{username}",
+    tag = "users",
+    summary = "A user's public profile",
+    params(("username" = String, Path)),
+    responses(openapi::Envelope)
+)]
 pub async fn get_profile(
     Path(username): Path<String>,
     State(data): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, ApiError> {
     // Query to find the user by username
     let user_id: Option<uuid::Uuid> =
-        sqlx::query_scalar!("SELECT id FROM users WHERE username = $1", username)
+        sqlx::query_scalar!(
+            "SELECT id FROM users WHERE username = $1 AND deactivated_at IS NULL",
+            username
+        )
             .fetch_optional(&data.db)
             .await
             .map_err(|_| ApiError::InternalServerError)?;
@@ -265,13 +426,22 @@ pub async fn get_profile(
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/user/get_all",
+    tag = "users",
+    summary = "Every active user",
+    responses(openapi::Envelope)
+)]
 pub async fn get_all_users(
     State(data): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let users: Vec<User> = sqlx::query_as!(User, "SELECT * FROM users")
-        .fetch_all(&data.db)
-        .await
-        .map_err(|_| ApiError::InternalServerError)?;
+    // private fields are only listed through the admin api
+    let users: Vec<FilterdUser> =
+        sqlx::query_as!(FilterdUser, "SELECT id, username FROM users WHERE deactivated_at IS NULL")
+            .fetch_all(&data.db)
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
 
     let response: GeneralResponse = GeneralResponse {
         status: Status::Success,
@@ -282,18 +452,19 @@ pub async fn get_all_users(
     Ok(Json(response))
 }
 
-pub async fn react_to_post(
-    State(data): State<Arc<AppState>>,
-    Extension(user): Extension<User>,
-    Path(post_id): Path<Uuid>,
-    Json(is_like): Json<LikePostSchema>,
-) -> Result<impl IntoResponse, ApiError> {
+// a user has at most one reaction per post, reacting again replaces it
+pub async fn record_reaction(
+    db: &PgPool,
+    post_id: Uuid,
+    user_id: Uuid,
+    is_like: bool,
+) -> Result<(), ApiError> {
     let existing_reaction = sqlx::query!(
         "SELECT id FROM post_reactions WHERE post_id = $1 AND user_id = $2",
         post_id,
-        user.id
+        user_id
     )
-    .fetch_optional(&data.db)
+    .fetch_optional(db)
     .await
     .map_err(|_| ApiError::InternalServerError)?;
 
@@ -301,10 +472,10 @@ pub async fn react_to_post(
         // Update the existing reaction
         sqlx::query!(
             "UPDATE post_reactions SET is_like = $1 WHERE id = $2",
-            is_like.is_like,
+            is_like,
             reaction.id
         )
-        .execute(&data.db)
+        .execute(db)
         .await
         .map_err(|_| ApiError::InternalServerError)?;
     } else {
@@ -312,13 +483,36 @@ pub async fn react_to_post(
         sqlx::query!(
             "INSERT INTO post_reactions (post_id, user_id, is_like) VALUES ($1, $2, $3)",
             post_id,
-            user.id,
-            is_like.is_like
+            user_id,
+            is_like
         )
-        .execute(&data.db)
+        .execute(db)
         .await
         .map_err(|_| ApiError::InternalServerError)?;
     }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/post/{post_id}/react",
+    tag = "posts",
+    summary = "Like or dislike a post",
+    request_body = LikePostSchema,
+    params(("post_id" = Uuid, Path)),
+    security(("session" = []), ("api_token" = ["reactions:write"])),
+    responses(openapi::Envelope)
+)]
+pub async fn react_to_post(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(post_id): Path<Uuid>,
+    Json(is_like): Json<LikePostSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    record_reaction(&data.db, post_id, user.id, is_like.is_like).await?;
+    metrics::REACTIONS
+        .with_label_values(&[if is_like.is_like { "like" } else { "dislike" }])
+        .inc();
 
     let counts = sqlx::query!(
         "SELECT 
@@ -344,8 +538,19 @@ pub async fn react_to_post(
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/post/{post_id}",
+    tag = "posts",
+    summary = "Delete a post",
+    params(("post_id" = Uuid, Path)),
+    security(("session" = []), ("api_token" = ["posts:write"])),
+    responses(openapi::Envelope)
+)]
 pub async fn delete_post(
     Extension(user): Extension<User>,
+    permissions: Permissions,
+    client: ClientInfo,
     State(data): State<Arc<AppState>>,
     Path(post_id): Path<Uuid>,
 ) -> Result<impl IntoResponse, ApiError> {
@@ -353,7 +558,8 @@ pub async fn delete_post(
         .fetch_one(&data.db)
         .await
         .map_err(|_| ApiError::InternalServerError)?;
-    if user.id != post_uuid {
+    // moderators and admins can remove anyone's post
+    if user.id != post_uuid && !permissions.has(Permission::DeleteAnyPost) {
         return Err(ApiError::Fail("not authorized to delete post".to_string()));
     }
     sqlx::query!("DELETE FROM posts WHERE id = $1", post_id)
@@ -361,6 +567,16 @@ pub async fn delete_post(
         .await
         .map_err(|_| ApiError::InternalServerError)?;
 
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "post.deleted",
+        Some(post_id),
+        json!({ "author_id": post_uuid, "moderated": user.id != post_uuid }),
+    )
+    .await;
+
     let response: GeneralResponse = GeneralResponse {
         status: Status::Success,
         message: "Post delleted".to_string(),
@@ -370,13 +586,336 @@ pub async fn delete_post(
     Ok(Json(response))
 }
 
-pub async fn is_logged_in() -> Result<impl IntoResponse, ApiError> {
+#[utoipa::path(
+    post,
+    path = "/auth/is_logged_in",
+    tag = "auth",
+    summary = "The session user and their permissions",
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn is_logged_in(permissions: Permissions) -> Result<impl IntoResponse, ApiError> {
+    let granted: Vec<&str> = permissions
+        .role
+        .permissions()
+        .iter()
+        .map(|permission| permission.as_str())
+        .collect();
     let response: GeneralResponse = GeneralResponse {
         status: Status::Success,
         message: "User logged in".to_string(),
         data: Some(json!({
             "is_logged_in": true,
+            "role": permissions.role.as_str(),
+            "permissions": granted,
+        })),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/me",
+    tag = "users",
+    summary = "The caller's account",
+    security(("session" = []), ("api_token" = ["profile:read"])),
+    responses(openapi::Envelope)
+)]
+pub async fn get_me(
+    Extension(user): Extension<User>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profile: Profile = sqlx::query_as!(
+        Profile,
+        "SELECT id, user_id, photo, bio, created_at, updated_at FROM profiles WHERE user_id = $1",
+        user.id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?
+    .ok_or_else(|| ApiError::Fail("Profile not found".to_string()))?;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Profile retrived".to_string(),
+        data: Some(json!({
+            "id": user.id,
+            "username": user.username,
+            "email": user.email,
+            "role": user.role,
+            "created_at": user.created_at,
+            "profile": profile,
+        })),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/tokens",
+    tag = "tokens",
+    summary = "Create an api token",
+    request_body = CreateApiTokenSchema,
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn create_api_token(
+    Extension(user): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<CreateApiTokenSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    let name = body.name.trim().to_string();
+    if name.is_empty() || name.len() > 100 {
+        return Err(ApiError::Fail("Token name must be 1 to 100 characters".to_string()));
+    }
+    if body.scopes.is_empty() {
+        return Err(ApiError::Fail("Token needs at least one scope".to_string()));
+    }
+    let expires_at = match body.expires_in_days {
+        Some(days) if !(1..=365).contains(&days) => {
+            return Err(ApiError::Fail("expires_in_days must be between 1 and 365".to_string()));
+        }
+        Some(days) => Some(Utc::now() + Duration::days(days)),
+        None => None,
+    };
+
+    let mut scopes: Vec<String> = body.scopes.iter().map(|scope| scope.as_str().to_string()).collect();
+    scopes.sort();
+    scopes.dedup();
+
+    let token = generate_token();
+    let token_id: Uuid = sqlx::query_scalar!(
+        "INSERT INTO api_tokens (user_id, name, token_hash, token_prefix, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+        user.id,
+        name,
+        hash_token(&token),
+        display_prefix(&token),
+        &scopes,
+        expires_at
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "token.created",
+        Some(token_id),
+        json!({ "name": name, "scopes": scopes }),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Token created, it will not be shown again".to_string(),
+        data: Some(json!({
+            "id": token_id,
+            "name": name,
+            "token": token,
+            "scopes": scopes,
+            "expires_at": expires_at,
         })),
     };
     Ok(Json(response))
 }
+
+#[utoipa::path(
+    get,
+    path = "/user/tokens",
+    tag = "tokens",
+    summary = "The caller's api tokens",
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn get_api_tokens(
+    Extension(user): Extension<User>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tokens: Vec<ApiToken> = sqlx::query_as!(
+        ApiToken,
+        "SELECT id, name, token_prefix, scopes, expires_at, last_used_at, created_at FROM api_tokens WHERE user_id = $1 ORDER BY created_at DESC",
+        user.id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "All tokens retrived".to_string(),
+        data: Some(json!(tokens)),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/user/tokens/{token_id}",
+    tag = "tokens",
+    summary = "Revoke an api token",
+    params(("token_id" = Uuid, Path)),
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn delete_api_token(
+    Extension(user): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(token_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let result = sqlx::query!(
+        "DELETE FROM api_tokens WHERE id = $1 AND user_id = $2",
+        token_id,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::Fail("Token not found".to_string()));
+    }
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "token.revoked",
+        Some(token_id),
+        json!({}),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Token revoked".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/user/me",
+    tag = "users",
+    summary = "Delete the caller's account",
+    request_body = DeleteAccountSchema,
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn delete_me(
+    Extension(user): Extension<User>,
+    session: Session,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<DeleteAccountSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    // re-authenticate, with the password when there is one
+    match (&user.password, &body.password) {
+        (Some(hash), Some(password)) => {
+            if !password::verify(&data.env, hash, password) {
+                return Err(ApiError::Fail("incorrect password".to_string()));
+            }
+        }
+        (Some(_), None) => {
+            return Err(ApiError::Fail("Missing field password".to_string()));
+        }
+        (None, _) => {
+            let authenticated_at = session
+                .get::<i64>("authenticated_at")
+                .await
+                .map_err(|_| ApiError::InternalServerError)?
+                .unwrap_or(0);
+            if Utc::now().timestamp() - authenticated_at > 5 * 60 {
+                return Err(ApiError::Fail("log in again to delete your account".to_string()));
+            }
+        }
+    }
+
+    let deactivated_at = sqlx::query_scalar!(
+        "UPDATE users SET deactivated_at = NOW(), updated_at = NOW() WHERE id = $1 RETURNING deactivated_at",
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?
+    .ok_or(ApiError::InternalServerError)?;
+    let purge_after = deactivated_at + Duration::days(data.env.account_grace_days.into());
+
+    revoke_sessions(&data.redis, user.id).await?;
+    session
+        .flush()
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "account.deactivated",
+        Some(user.id),
+        json!({ "purge_after": purge_after }),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Account deactivated, log in before purge_after to restore it".to_string(),
+        data: Some(json!({ "purge_after": purge_after })),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/me/password",
+    tag = "users",
+    summary = "Change the caller's password",
+    request_body = ChangePasswordSchema,
+    security(("session" = [])),
+    responses(openapi::Envelope)
+)]
+pub async fn change_password(
+    Extension(user): Extension<User>,
+    session: Session,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<ChangePasswordSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    // re-authenticate, an account from an identity provider may set its first
+    // password shortly after logging in
+    match (&user.password, &body.current_password) {
+        (Some(hash), Some(password)) => {
+            if !password::verify(&data.env, hash, password) {
+                return Err(ApiError::Fail("incorrect password".to_string()));
+            }
+        }
+        (Some(_), None) => {
+            return Err(ApiError::Fail("Missing field current_password".to_string()));
+        }
+        (None, _) => {
+            let authenticated_at = session
+                .get::<i64>("authenticated_at")
+                .await
+                .map_err(|_| ApiError::InternalServerError)?
+                .unwrap_or(0);
+            if Utc::now().timestamp() - authenticated_at > 5 * 60 {
+                return Err(ApiError::Fail("log in again to set a password".to_string()));
+            }
+        }
+    }
+
+    accounts::set_password(&data, &client, &user, &body.new_password, "changed").await?;
+    // every session was revoked, this one carries on under a new id
+    start_session(&session, &data.redis, &client, user.id).await?;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Password changed, other sessions were logged out".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
@@ -0,0 +1,90 @@
+use crate::{
+    openapi,
+    response::{GeneralResponse, Status},
+    AppState,
+};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::{json, Value};
+use std::{
+    future::Future,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use tower_sessions_redis_store::fred::prelude::*;
+
+// a dependency slower than this counts as down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+async fn check<E: std::fmt::Display>(probe: impl Future<Output = Result<(), E>>) -> (bool, Value) {
+    let started = Instant::now();
+    let (up, error) = match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(Ok(())) => (true, None),
+        Ok(Err(err)) => (false, Some(err.to_string())),
+        Err(_) => (false, Some("timed out".to_string())),
+    };
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let mut report = json!({
+        "status": if up { "up" } else { "down" },
+        "latency_ms": (latency_ms * 100.0).round() / 100.0,
+    });
+    if let Some(error) = error {
+        report["error"] = json!(error);
+    }
+    (up, report)
+}
+
+// GET /healthz, the process is running and serving requests
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "operations",
+    summary = "Liveness",
+    responses(openapi::Envelope)
+)]
+pub async fn healthz() -> impl IntoResponse {
+    Json(GeneralResponse {
+        status: Status::Success,
+        message: "alive".to_string(),
+        data: None,
+    })
+}
+
+// GET /readyz, 503 while shutting down or when Postgres or Redis do not answer
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    summary = "Readiness of the database and Redis",
+    responses(openapi::Ready)
+)]
+pub async fn readyz(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    let ((postgres_up, postgres), (redis_up, redis)) = tokio::join!(
+        check(async {
+            sqlx::query("SELECT 1").execute(&data.db).await.map(|_| ())
+        }),
+        check(data.redis.ping::<()>()),
+    );
+    let draining = data.draining.load(Ordering::Relaxed);
+    let ready = postgres_up && redis_up && !draining;
+
+    let response = GeneralResponse {
+        status: if ready { Status::Success } else { Status::Error },
+        message: if draining {
+            "draining".to_string()
+        } else if ready {
+            "ready".to_string()
+        } else {
+            "a dependency is down".to_string()
+        },
+        data: Some(json!({
+            "draining": draining,
+            "checks": { "postgres": postgres, "redis": redis },
+        })),
+    };
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(response))
+}
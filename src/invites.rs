@@ -0,0 +1,205 @@
+use crate::{
+    audit,
+    client::ClientInfo,
+    config::{Config, RegistrationMode},
+    model::{InviteCode, User},
+    openapi,
+    response::{ApiError, AppJson, GeneralResponse, Status},
+    schema::CreateInviteSchema,
+    tokens::{display_prefix, hash_token},
+    AppState,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const INVITE_PREFIX: &str = "inv_";
+
+fn generate_code() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", INVITE_PREFIX, hex::encode(bytes))
+}
+
+// an empty allowlist lets every domain through
+pub fn email_domain_allowed(config: &Config, email: &str) -> bool {
+    if config.registration_domains.is_empty() {
+        return true;
+    }
+    let domain = email.rsplit_once('@').map(|(_, domain)| domain.to_ascii_lowercase());
+    domain.is_some_and(|domain| config.registration_domains.contains(&domain))
+}
+
+// for sign ups that can not carry an invite code, like OIDC
+pub fn check_open_registration(config: &Config, email: &str) -> Result<(), ApiError> {
+    match config.registration_mode {
+        RegistrationMode::Open => {}
+        RegistrationMode::InviteOnly => {
+            return Err(ApiError::Fail("registration is invite only".to_string()));
+        }
+        RegistrationMode::Closed => {
+            return Err(ApiError::Fail("registration is closed".to_string()));
+        }
+    }
+    if !email_domain_allowed(config, email) {
+        return Err(ApiError::Fail(
+            "registration is not open for this email domain".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// takes one use of the code, run it in the registration transaction so a
+// failed sign up gives the use back
+pub async fn redeem(conn: &mut PgConnection, code: &str) -> Result<Uuid, ApiError> {
+    sqlx::query_scalar!(
+        "UPDATE invite_codes SET uses = uses + 1
+        WHERE code_hash = $1 AND uses < max_uses AND (expires_at IS NULL OR expires_at > NOW())
+        RETURNING id",
+        hash_token(code)
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?
+    .ok_or_else(|| ApiError::Fail("invite code is invalid or used up".to_string()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/invites",
+    tag = "admin",
+    summary = "Create an invite code",
+    request_body = CreateInviteSchema,
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_create_invite(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<CreateInviteSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    let max_uses = body.max_uses.unwrap_or(1);
+    if max_uses < 1 {
+        return Err(ApiError::Fail("max_uses must be at least 1".to_string()));
+    }
+    let expires_at = match body.expires_in_days {
+        Some(days) if days > 0 => Some(Utc::now() + Duration::days(days)),
+        Some(_) => return Err(ApiError::Fail("expires_in_days must be positive".to_string())),
+        None => None,
+    };
+
+    let code = generate_code();
+    let invite_id: Uuid = sqlx::query_scalar!(
+        "INSERT INTO invite_codes (code_hash, code_prefix, created_by, max_uses, expires_at)
+        VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        hash_token(&code),
+        display_prefix(&code),
+        admin.id,
+        max_uses,
+        expires_at
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.invite_created",
+        Some(invite_id),
+        json!({ "max_uses": max_uses, "expires_at": expires_at }),
+    )
+    .await;
+
+    // the code is only ever shown here
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Invite created".to_string(),
+        data: Some(json!({
+            "id": invite_id,
+            "code": code,
+            "max_uses": max_uses,
+            "expires_at": expires_at,
+        })),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/invites",
+    tag = "admin",
+    summary = "Invite codes",
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_get_invites(
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let invites: Vec<InviteCode> = sqlx::query_as!(
+        InviteCode,
+        "SELECT id, code_prefix, created_by, max_uses, uses, expires_at, created_at
+        FROM invite_codes ORDER BY created_at DESC"
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| ApiError::InternalServerError)?;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "All invites retrived".to_string(),
+        data: Some(json!(invites)),
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/invites/{invite_id}",
+    tag = "admin",
+    summary = "Delete an invite code",
+    params(("invite_id" = Uuid, Path)),
+    security(("session" = ["users:manage"])),
+    responses(openapi::Envelope)
+)]
+pub async fn admin_delete_invite(
+    Extension(admin): Extension<User>,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Path(invite_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = sqlx::query!("DELETE FROM invite_codes WHERE id = $1", invite_id)
+        .execute(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::Fail("Invite not found".to_string()));
+    }
+
+    audit::record(
+        &data.db,
+        &client,
+        Some(admin.id),
+        "admin.invite_revoked",
+        Some(invite_id),
+        json!({}),
+    )
+    .await;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Invite revoked".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
@@ -0,0 +1,256 @@
+use crate::{audit, client::ClientInfo, exports, shutdown, AppState};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::watch, task::JoinHandle};
+use uuid::Uuid;
+
+// jobs finish the work in hand and return once shutdown is set
+pub fn spawn(data: Arc<AppState>, shutdown: watch::Receiver<bool>) -> Vec<JoinHandle<()>> {
+    vec![
+        tokio::spawn(purge_deactivated_accounts(data.clone(), shutdown.clone())),
+        tokio::spawn(run_data_exports(data, shutdown)),
+    ]
+}
+
+// deactivated accounts are hard deleted once the grace period is over,
+// everything they own goes with them through ON DELETE CASCADE
+async fn purge_deactivated_accounts(data: Arc<AppState>, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.wait_for(|stop| *stop) => break,
+        }
+        // export archives live on disk, the cascade would only drop their rows
+        if let Ok(exports) = sqlx::query_scalar!(
+            "DELETE FROM data_exports WHERE user_id IN (
+                SELECT id FROM users WHERE deactivated_at < NOW() - make_interval(days => $1)
+            ) RETURNING file_path",
+            data.env.account_grace_days
+        )
+        .fetch_all(&data.db)
+        .await
+        {
+            for file_path in exports.into_iter().flatten() {
+                let _ = tokio::fs::remove_file(file_path).await;
+            }
+        }
+
+        let purged: Vec<Uuid> = match sqlx::query_scalar!(
+            "DELETE FROM users WHERE deactivated_at < NOW() - make_interval(days => $1) RETURNING id",
+            data.env.account_grace_days
+        )
+        .fetch_all(&data.db)
+        .await
+        {
+            Ok(purged) => purged,
+            Err(err) => {
+                tracing::error!("failed to purge deactivated accounts: {}", err);
+                continue;
+            }
+        };
+
+        for user_id in purged {
+            audit::record(
+                &data.db,
+                &ClientInfo::default(),
+                None,
+                "account.purged",
+                Some(user_id),
+                json!({ "grace_days": data.env.account_grace_days }),
+            )
+            .await;
+        }
+    }
+}
+
+// the worker building an export bumps heartbeat_at this often, an export
+// whose heartbeat is older than STALE_EXPORT_MINUTES was claimed by an instance
+// that crashed or was killed and goes back in the queue. A large export that
+// is still building keeps beating and is left alone
+const HEARTBEAT_SECS: u64 = 60;
+const STALE_EXPORT_MINUTES: i32 = 5;
+
+// picks up queued exports one at a time, SKIP LOCKED keeps several instances
+// from building the same one
+async fn run_data_exports(data: Arc<AppState>, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.wait_for(|stop| *stop) => break,
+        }
+        remove_expired_exports(&data).await;
+        requeue_stale_exports(&data).await;
+
+        // the rest of the queue is left for the next instance
+        while !*shutdown.borrow() {
+            let claimed = match sqlx::query!(
+                "UPDATE data_exports SET status = 'running', heartbeat_at = NOW()
+                WHERE id = (
+                    SELECT id FROM data_exports WHERE status = 'pending'
+                    ORDER BY created_at LIMIT 1 FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, user_id"
+            )
+            .fetch_optional(&data.db)
+            .await
+            {
+                Ok(Some(claimed)) => claimed,
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::error!("failed to claim data export: {}", err);
+                    break;
+                }
+            };
+            // an export still building at the shutdown deadline goes back in the
+            // queue instead of staying claimed by an instance that is gone
+            let deadline = shutdown::deadline(
+                shutdown.clone(),
+                Duration::from_secs(data.env.shutdown_timeout_secs),
+            );
+            tokio::select! {
+                _ = run_data_export(&data, claimed.id, claimed.user_id) => {}
+                _ = heartbeat(&data, claimed.id) => {}
+                _ = deadline => {
+                    requeue_data_export(&data, claimed.id).await;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// runs next to the export until it is done, never returns on its own
+async fn heartbeat(data: &AppState, export_id: Uuid) {
+    let mut interval = tokio::time::interval(Duration::from_secs(HEARTBEAT_SECS));
+    // the claim set the first one
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(err) = sqlx::query!(
+            "UPDATE data_exports SET heartbeat_at = NOW() WHERE id = $1 AND status = 'running'",
+            export_id
+        )
+        .execute(&data.db)
+        .await
+        {
+            tracing::error!(%export_id, error = %err, "failed to record data export heartbeat");
+        }
+    }
+}
+
+async fn requeue_data_export(data: &AppState, export_id: Uuid) {
+    let requeued = sqlx::query!(
+        "UPDATE data_exports SET status = 'pending', heartbeat_at = NULL WHERE id = $1 AND status = 'running'",
+        export_id
+    )
+    .execute(&data.db)
+    .await;
+    match requeued {
+        Ok(_) => tracing::warn!(%export_id, "data export interrupted by shutdown, queued again"),
+        Err(err) => tracing::error!(%export_id, error = %err, "failed to queue data export again"),
+    }
+}
+
+async fn run_data_export(data: &AppState, export_id: Uuid, user_id: Uuid) {
+    let path = match exports::build_export(data, export_id, user_id).await {
+        Ok(path) => path,
+        Err(_) => {
+            tracing::error!("failed to build data export {}", export_id);
+            let _ = sqlx::query!(
+                "UPDATE data_exports SET status = 'failed', completed_at = NOW() WHERE id = $1",
+                export_id
+            )
+            .execute(&data.db)
+            .await;
+            return;
+        }
+    };
+
+    let expires_at = exports::expires_at();
+    let marked = sqlx::query!(
+        "UPDATE data_exports SET status = 'ready', file_path = $1, completed_at = NOW(), expires_at = $2 WHERE id = $3",
+        path.to_string_lossy().to_string(),
+        expires_at,
+        export_id
+    )
+    .execute(&data.db)
+    .await;
+    if let Err(err) = marked {
+        tracing::error!("failed to finish data export {}: {}", export_id, err);
+        return;
+    }
+
+    let email: Option<String> = sqlx::query_scalar!("SELECT email FROM users WHERE id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await
+        .ok()
+        .flatten();
+    if let Some(email) = email {
+        let link = exports::download_url(data, export_id, expires_at);
+        let sent = data
+            .mailer
+            .send(
+                &email,
+                "Your data export is ready",
+                &format!(
+                    "Your data export is ready, download it before {}:\n\n{}\n",
+                    expires_at.format("%Y-%m-%d %H:%M UTC"),
+                    link
+                ),
+            )
+            .await;
+        if sent.is_err() {
+            tracing::error!("failed to mail data export {}", export_id);
+        }
+    }
+
+    audit::record(
+        &data.db,
+        &ClientInfo::default(),
+        None,
+        "account.export_ready",
+        Some(export_id),
+        json!({ "user_id": user_id }),
+    )
+    .await;
+}
+
+async fn requeue_stale_exports(data: &AppState) {
+    let requeued = sqlx::query_scalar!(
+        "UPDATE data_exports SET status = 'pending', heartbeat_at = NULL
+        WHERE status = 'running'
+        AND (heartbeat_at IS NULL OR heartbeat_at < NOW() - make_interval(mins => $1))
+        RETURNING id",
+        STALE_EXPORT_MINUTES
+    )
+    .fetch_all(&data.db)
+    .await;
+    match requeued {
+        Ok(requeued) => {
+            for export_id in requeued {
+                tracing::warn!(%export_id, "data export was left running, queued again");
+            }
+        }
+        Err(err) => tracing::error!(error = %err, "failed to queue stale data exports again"),
+    }
+}
+
+async fn remove_expired_exports(data: &AppState) {
+    let expired = match sqlx::query!(
+        "DELETE FROM data_exports WHERE expires_at < NOW() RETURNING file_path"
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(expired) => expired,
+        Err(err) => {
+            tracing::error!("failed to remove expired data exports: {}", err);
+            return;
+        }
+    };
+    for file_path in expired.into_iter().filter_map(|export| export.file_path) {
+        let _ = tokio::fs::remove_file(file_path).await;
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    accounts,
+    audit,
+    client::ClientInfo,
+    metrics,
+    model::User,
+    openapi,
+    rate_limit,
+    response::{ApiError, AppJson, GeneralResponse, Status},
+    route::API_PREFIX,
+    schema::{MagicLinkQuery, MagicLinkSchema},
+    sessions::start_session,
+    signing,
+    tokens::hash_token,
+    AppState,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use tower_sessions::Session;
+use tower_sessions_redis_store::fred::prelude::*;
+use uuid::Uuid;
+
+const LINK_TTL_SECS: i64 = 15 * 60;
+const BINDING_KEY: &str = "magic_link_binding";
+
+// deletes the link only if it still holds what the caller read, so two
+// clicks racing each other can not both use it. Password reset links too
+pub const CLAIM_LINK: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end";
+
+// stored under the token hash, claimed once the browser binding matched
+#[derive(Serialize, Deserialize)]
+struct PendingLink {
+    user_id: Uuid,
+    binding: String,
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn link_key(token: &str) -> String {
+    format!("magic_link:{}", hash_token(token))
+}
+
+fn link_payload(token: &str, expires: i64) -> String {
+    format!("magic-link:{}:{}", token, expires)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/magic-link",
+    tag = "auth",
+    summary = "Email a sign in link",
+    request_body = MagicLinkSchema,
+    responses(openapi::Envelope)
+)]
+pub async fn request_magic_link(
+    session: Session,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<MagicLinkSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    let email = body.email.trim().to_ascii_lowercase();
+    if email.is_empty() {
+        return Err(ApiError::Fail("Missing field email".to_string()));
+    }
+
+    let ip = client.ip.clone().unwrap_or_default();
+    let allowed = rate_limit::hit(&data.redis, &format!("magic_link:email:{}", email), 3, LINK_TTL_SECS).await?
+        && rate_limit::hit(&data.redis, &format!("magic_link:ip:{}", ip), 10, LINK_TTL_SECS).await?;
+    if !allowed {
+        return Err(ApiError::Fail("too many login links requested, try again later".to_string()));
+    }
+
+    let user: Option<User> = sqlx::query_as!(User, "SELECT * FROM users WHERE email = $1", email)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+    // the answer is the same whether or not the email has an account
+    if let Some(user) = user {
+        let binding = random_token();
+        session
+            .insert(BINDING_KEY, &binding)
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
+
+        let token = random_token();
+        let pending = json!(PendingLink {
+            user_id: user.id,
+            binding: hash_token(&binding),
+        })
+        .to_string();
+        data.redis
+            .set::<(), _, _>(
+                link_key(&token),
+                pending,
+                Some(Expiration::EX(LINK_TTL_SECS)),
+                None,
+                false,
+            )
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
+
+        let expires = Utc::now().timestamp() + LINK_TTL_SECS;
+        let sig = signing::sign(&data.env.app_secret, &link_payload(&token, expires));
+        let link = format!(
+            "{}{}/auth/magic-link/verify?token={}&expires={}&sig={}",
+            data.env.public_url, API_PREFIX, token, expires, sig
+        );
+        data.mailer
+            .send(
+                &user.email,
+                "Your login link",
+                &format!(
+                    "Hi {},\n\nopen this link in the same browser to log in, it expires in 15 minutes:\n\n{}\n\nIf you did not ask for it you can ignore this mail.\n",
+                    user.username, link
+                ),
+            )
+            .await?;
+
+        audit::record(
+            &data.db,
+            &client,
+            Some(user.id),
+            "auth.magic_link_requested",
+            Some(user.id),
+            json!({}),
+        )
+        .await;
+    }
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "If the email has an account a login link was sent".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/magic-link/verify",
+    tag = "auth",
+    summary = "Sign in from an emailed link",
+    params(MagicLinkQuery),
+    responses(openapi::Redirect)
+)]
+pub async fn verify_magic_link(
+    session: Session,
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<MagicLinkQuery>,
+) -> Result<Redirect, ApiError> {
+    if !signing::verify(
+        &data.env.app_secret,
+        &link_payload(&query.token, query.expires),
+        &query.sig,
+    ) {
+        return Err(ApiError::Fail("invalid login link".to_string()));
+    }
+    if query.expires < Utc::now().timestamp() {
+        return Err(ApiError::Fail("login link expired".to_string()));
+    }
+
+    // a plain read first, a mail scanner prefetching the link or a click in
+    // another browser must not use it up
+    let stored: Option<String> = data
+        .redis
+        .get(link_key(&query.token))
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    let stored = stored.ok_or_else(|| ApiError::Fail("login link already used".to_string()))?;
+    let pending: PendingLink =
+        serde_json::from_str(&stored).map_err(|_| ApiError::InternalServerError)?;
+
+    // the session cookie is SameSite=Lax, so it comes along when the link is
+    // opened from webmail
+    let binding = session
+        .get::<String>(BINDING_KEY)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if binding.map(|binding| hash_token(&binding)) != Some(pending.binding) {
+        audit::record(
+            &data.db,
+            &client,
+            Some(pending.user_id),
+            "auth.login_failed",
+            Some(pending.user_id),
+            json!({ "reason": "magic_link_other_browser" }),
+        )
+        .await;
+        return Err(ApiError::Fail(
+            "open the login link in the browser that requested it".to_string(),
+        ));
+    }
+
+    let claimed: i64 = data
+        .redis
+        .eval(CLAIM_LINK, link_key(&query.token), stored)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if claimed != 1 {
+        return Err(ApiError::Fail("login link already used".to_string()));
+    }
+    session
+        .remove::<String>(BINDING_KEY)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+    let user = sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", pending.user_id)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?
+        .ok_or_else(|| ApiError::Fail("user does not exist".to_string()))?;
+    if user.is_suspended() {
+        audit::record(
+            &data.db,
+            &client,
+            Some(user.id),
+            "auth.login_failed",
+            Some(user.id),
+            json!({ "reason": "suspended", "method": "magic_link" }),
+        )
+        .await;
+        return Err(ApiError::Fail("account is suspended".to_string()));
+    }
+
+    accounts::reactivate(&data, &client, &user).await?;
+    start_session(&session, &data.redis, &client, user.id).await?;
+    metrics::LOGINS.with_label_values(&["magic_link"]).inc();
+    audit::record(
+        &data.db,
+        &client,
+        Some(user.id),
+        "auth.login",
+        Some(user.id),
+        json!({ "method": "magic_link" }),
+    )
+    .await;
+
+    Ok(Redirect::to(&data.env.login_redirect))
+}
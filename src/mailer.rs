@@ -0,0 +1,66 @@
+use crate::{config::Config, response::ApiError};
+use axum::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use std::sync::Arc;
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), ApiError>;
+}
+
+// development mailer, the mail ends up in the server log
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), ApiError> {
+        tracing::info!(to, subject, body, "mail not sent, MAILER=log");
+        Ok(())
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), ApiError> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|_| ApiError::Fail("Email is not valid".to_string()))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .body(body.to_string())
+            .map_err(|_| ApiError::InternalServerError)?;
+        self.transport.send(message).await.map_err(|err| {
+            tracing::error!("failed to send mail: {}", err);
+            ApiError::InternalServerError
+        })?;
+        Ok(())
+    }
+}
+
+pub fn from_config(config: &Config) -> Arc<dyn Mailer> {
+    match config.mailer.as_str() {
+        "smtp" => {
+            let mut transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)
+                .expect("SMTP_HOST must be a valid host")
+                .port(config.smtp_port);
+            if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+                transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+            }
+            Arc::new(SmtpMailer {
+                transport: transport.build(),
+                from: config.mail_from.parse().expect("MAIL_FROM must be a valid mailbox"),
+            })
+        }
+        _ => Arc::new(LogMailer),
+    }
+}
@@ -1,23 +1,62 @@
+mod accounts;
+mod admin;
+mod audit;
+mod cli;
+mod client;
 mod config;
+mod csrf;
+mod deprecation;
+mod exports;
 mod filters;
 mod handlers;
+mod health;
+mod invites;
+mod jobs;
+mod magic_link;
+mod mailer;
+mod metrics;
 mod model;
+mod oidc;
+mod openapi;
+mod password;
+mod password_reset;
+mod pow;
+mod rbac;
 mod response;
+mod rate_limit;
+mod recorder;
+mod replay;
+mod request_id;
 mod route;
 mod schema;
+mod security_headers;
+mod seed;
 mod session_auth;
+mod sessions;
+mod shutdown;
+mod signing;
+mod telemetry;
+mod tokens;
 use axum::http::{
-    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
-    HeaderValue, Method,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, LINK},
+    HeaderName, HeaderValue, Method,
 };
+use axum::{middleware, Router};
+use clap::Parser;
+use cli::{Cli, Command};
 use config::Config;
 use dotenv::dotenv;
 use route::create_router;
+use security_headers::{asset_headers, security_headers};
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use std::sync::Arc;
+use std::{
+    net::SocketAddr,
+    sync::{atomic::AtomicBool, Arc},
+};
 use time::Duration;
+use tokio::sync::watch;
 use tower_http::{cors::CorsLayer, services::ServeDir};
-use tower_sessions::{Expiry, SessionManagerLayer};
+use tower_sessions::{cookie::SameSite, Expiry, SessionManagerLayer};
 use tower_sessions_redis_store::{fred::prelude::*, RedisStore};
 use tower_http::trace::TraceLayer;
 
@@ -25,67 +64,275 @@ use tower_http::trace::TraceLayer;
 pub struct AppState {
     db: Pool<Postgres>,
     env: Config,
+    redis: RedisPool,
+    http: reqwest::Client,
+    mailer: Arc<dyn mailer::Mailer>,
+    breached_passwords: password::BreachedPasswords,
+    recorder: Option<recorder::Recorder>,
+    // set once shutdown starts so /readyz fails and traffic moves elsewhere
+    draining: AtomicBool,
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
-    let config = Config::init();
+    match Cli::parse().command.unwrap_or(Command::Serve { migrate: false }) {
+        Command::Serve { migrate } => serve(migrate).await,
+        Command::Migrate { action } => cli::migrate(action).await,
+        Command::CreateAdmin {
+            username,
+            email,
+            password,
+        } => cli::create_admin(username, email, password).await,
+        Command::Seed {
+            dir,
+            allow_weak_passwords,
+            users,
+            posts,
+        } => match (users, posts) {
+            (Some(users), Some(posts)) => seed::generate(users, posts).await,
+            _ => seed::load_fixtures(dir, allow_weak_passwords).await,
+        },
+        Command::Replay {
+            file,
+            target,
+            password,
+            token,
+            clients,
+        } => replay::replay(file, target, password, token, clients).await,
+        Command::CheckConfig => cli::check_config(),
+        Command::BenchArgon2 { target_ms } => {
+            password::benchmark(std::time::Duration::from_millis(target_ms))
+        }
+    }
+}
+
+pub fn load_config() -> Config {
+    let config = match Config::init() {
+        Ok(config) => config,
+        Err(err) => cli::fail(format!("Invalid configuration: {}", err)),
+    };
 
-    let pool = match PgPoolOptions::new()
-        .max_connections(10)
+    telemetry::init(&config);
+    config
+}
+
+pub async fn connect_db(config: &Config) -> Pool<Postgres> {
+    match PgPoolOptions::new()
+        .max_connections(config.database_max_connections)
         .connect(&config.database_url)
         .await
     {
         Ok(pool) => {
-            println!("✅ Connection to db successful!");
+            tracing::info!("connected to the database");
             pool
         }
         Err(err) => {
-            println!("🔥 Failed to connect to the database: {:?}", err);
+            tracing::error!(error = %err, "failed to connect to the database");
             std::process::exit(1);
         }
-    };
+    }
+}
 
-    let redis_pool = match RedisPool::new(RedisConfig::default(), None, None, None, 6) {
+pub fn load_breached_passwords(config: &Config) -> password::BreachedPasswords {
+    match &config.breached_passwords_dir {
+        Some(path) => match password::BreachedPasswords::open(path) {
+            Ok(breached) => {
+                tracing::info!(path = %path, "checking passwords against breached password ranges");
+                breached
+            }
+            Err(err) => {
+                tracing::error!(path = %path, error = %err, "failed to open breached passwords");
+                std::process::exit(1);
+            }
+        },
+        None => password::BreachedPasswords::default(),
+    }
+}
+
+async fn serve(migrate: bool) {
+    let config = load_config();
+    let pool = connect_db(&config).await;
+
+    if migrate || config.auto_migrate {
+        match cli::MIGRATOR.run(&pool).await {
+            Ok(()) => tracing::info!("migrations are up to date"),
+            Err(err) => {
+                tracing::error!(error = %err, "failed to run migrations");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // the url was checked by Config::init
+    let mut redis_config = RedisConfig::from_url(&config.redis_url).unwrap();
+    // a span per command, exported along with the request that sent it
+    redis_config.tracing = TracingConfig::new(config.otlp_endpoint.is_some());
+    let redis_pool = match RedisPool::new(redis_config, None, None, None, config.redis_pool_size) {
         Ok(pool) => {
-            println!("✅ Connection to redis successfull!");
+            tracing::info!("created the redis pool");
             pool
         }
         Err(err) => {
-            println!("🔥 Failed to connect to redis: {:?}", err);
+            tracing::error!(error = %err, "failed to create the redis pool");
             std::process::exit(1);
         }
     };
     let redis_conn = redis_pool.connect();
 
-    let session_store = RedisStore::new(redis_pool);
+    let session_store = RedisStore::new(redis_pool.clone());
+    // Lax, the redirect back from an identity provider or a link opened from
+    // webmail is a cross-site navigation and has to carry the session
     let session_layer = SessionManagerLayer::new(session_store)
         .with_secure(false)
+        .with_same_site(SameSite::Lax)
         .with_expiry(Expiry::OnInactivity(Duration::minutes(10)));
 
     let cors = CorsLayer::new()
-        .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
+        .allow_origin(
+            config
+                .cors_origins
+                .iter()
+                .map(|origin| origin.parse::<HeaderValue>().unwrap())
+                .collect::<Vec<HeaderValue>>(),
+        )
         .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_credentials(true)
-        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE]);
+        .allow_headers([
+            AUTHORIZATION,
+            ACCEPT,
+            CONTENT_TYPE,
+            HeaderName::from_static(csrf::CSRF_HEADER),
+            HeaderName::from_static(request_id::REQUEST_ID_HEADER),
+        ])
+        .expose_headers([
+            HeaderName::from_static(request_id::REQUEST_ID_HEADER),
+            HeaderName::from_static(deprecation::DEPRECATION_HEADER),
+            HeaderName::from_static(deprecation::SUNSET_HEADER),
+            LINK,
+        ]);
 
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    let breached_passwords = load_breached_passwords(&config);
+    metrics::init();
+    let recorder = if config.record_requests {
+        match recorder::Recorder::open(&config.record_file).await {
+            Ok(recorder) => {
+                tracing::warn!(file = %config.record_file, "recording requests");
+                Some(recorder)
+            }
+            Err(err) => {
+                tracing::error!(file = %config.record_file, error = %err, "failed to open the record file");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
 
-    let app = create_router(Arc::new(AppState {
+    let app_state = Arc::new(AppState {
         db: pool.clone(),
         env: config.clone(),
-    }))
-    .nest_service("/assets", ServeDir::new("./assets"))
+        redis: redis_pool,
+        http: reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap(),
+        mailer: mailer::from_config(&config),
+        breached_passwords,
+        recorder,
+        draining: AtomicBool::new(false),
+    });
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut jobs = jobs::spawn(app_state.clone(), shutdown_rx.clone());
+
+    let assets = Router::new()
+        .nest_service("/assets", ServeDir::new("./assets"))
+        .layer(middleware::from_fn_with_state(app_state.clone(), asset_headers));
+
+    let app = create_router(app_state.clone())
+    .merge(assets)
+    .layer(middleware::from_fn_with_state(app_state.clone(), security_headers))
     .layer(cors)
     .layer(session_layer)
-    .layer(TraceLayer::new_for_http());
+    .layer(
+        TraceLayer::new_for_http()
+            .make_span_with(telemetry::make_span)
+            .on_response(telemetry::OnResponse),
+    )
+    // outermost so the trace span and every response see the id
+    .layer(middleware::from_fn(request_id::request_id));
+
+    let listener = match tokio::net::TcpListener::bind(config.bind_address).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            tracing::error!(address = %config.bind_address, error = %err, "failed to bind");
+            std::process::exit(1);
+        }
+    };
+    tracing::info!(address = %config.bind_address, "server started");
+    tokio::spawn(shutdown::on_signal(app_state.clone(), shutdown_tx));
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown::requested(shutdown_rx.clone()));
+
+    let drained = async {
+        if let Err(err) = server.await {
+            tracing::error!(error = %err, "server error");
+        }
+        for job in jobs.iter_mut() {
+            let _ = job.await;
+        }
+    };
+    let timed_out = tokio::select! {
+        _ = drained => {
+            tracing::info!("in-flight requests finished");
+            false
+        }
+        _ = shutdown::deadline(
+            shutdown_rx,
+            std::time::Duration::from_secs(config.shutdown_timeout_secs),
+        ) => {
+            tracing::warn!("shutdown timeout reached, dropping in-flight requests");
+            true
+        }
+    };
+    if timed_out {
+        // the jobs saw the same deadline and are putting claimed work back,
+        // whatever is still running after that is cut off
+        let stopped = tokio::time::timeout(shutdown::CLOSE_TIMEOUT, async {
+            for job in jobs.iter_mut().filter(|job| !job.is_finished()) {
+                let _ = job.await;
+            }
+        })
+        .await;
+        if stopped.is_err() {
+            tracing::warn!("background jobs did not stop, aborting them");
+            for job in &jobs {
+                job.abort();
+            }
+        }
+    }
 
-    println!("✅ Server started successfully");
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-    redis_conn.await.unwrap().unwrap();
+    // a hung query keeps its connection checked out and close would wait on it
+    if tokio::time::timeout(shutdown::CLOSE_TIMEOUT, app_state.db.close())
+        .await
+        .is_err()
+    {
+        tracing::warn!("database connections still in use, closing anyway");
+    }
+    // quit waits on the server, an unreachable one must not hold up the exit
+    let closed = tokio::time::timeout(shutdown::CLOSE_TIMEOUT, async {
+        app_state.redis.quit().await?;
+        let _ = redis_conn.await;
+        Ok::<(), RedisError>(())
+    })
+    .await;
+    if !matches!(closed, Ok(Ok(()))) {
+        tracing::warn!("redis did not close cleanly");
+    }
+    tracing::info!("server stopped");
+    telemetry::shutdown();
 }
@@ -0,0 +1,194 @@
+use crate::{openapi, telemetry::MatchedRoute, AppState};
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
+    register_int_counter_with_registry, register_int_gauge_with_registry, Encoder, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, Registry, TextEncoder,
+};
+use std::{sync::Arc, time::Instant};
+use tower_sessions_redis_store::fred::interfaces::MetricsInterface;
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec_with_registry!(
+        "http_requests_total",
+        "HTTP requests by matched route, method and status",
+        &["route", "method", "status"],
+        REGISTRY
+    )
+    .unwrap();
+    static ref HTTP_DURATION: HistogramVec = register_histogram_vec_with_registry!(
+        "http_request_duration_seconds",
+        "HTTP request latency by matched route, method and status",
+        &["route", "method", "status"],
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0],
+        REGISTRY
+    )
+    .unwrap();
+    static ref DB_POOL_SIZE: IntGauge = register_int_gauge_with_registry!(
+        "db_pool_connections",
+        "Open Postgres connections",
+        REGISTRY
+    )
+    .unwrap();
+    static ref DB_POOL_IDLE: IntGauge = register_int_gauge_with_registry!(
+        "db_pool_idle_connections",
+        "Open Postgres connections not in use",
+        REGISTRY
+    )
+    .unwrap();
+    static ref DB_POOL_MAX: IntGauge = register_int_gauge_with_registry!(
+        "db_pool_max_connections",
+        "DATABASE_MAX_CONNECTIONS",
+        REGISTRY
+    )
+    .unwrap();
+    // fred keeps the latency of every command, each scrape moves it over
+    static ref REDIS_COMMANDS: IntCounter = register_int_counter_with_registry!(
+        "redis_commands_total",
+        "Redis commands that got a response",
+        REGISTRY
+    )
+    .unwrap();
+    static ref REDIS_LATENCY_MS: IntCounter = register_int_counter_with_registry!(
+        "redis_command_latency_milliseconds_total",
+        "Summed Redis command latency, divide by redis_commands_total for the mean",
+        REGISTRY
+    )
+    .unwrap();
+    static ref REDIS_MAX_LATENCY_MS: IntGauge = register_int_gauge_with_registry!(
+        "redis_command_max_latency_milliseconds",
+        "Slowest Redis command since the previous scrape",
+        REGISTRY
+    )
+    .unwrap();
+    pub static ref REGISTRATIONS: IntCounterVec = register_int_counter_vec_with_registry!(
+        "registrations_total",
+        "Accounts created by sign up method",
+        &["method"],
+        REGISTRY
+    )
+    .unwrap();
+    pub static ref LOGINS: IntCounterVec = register_int_counter_vec_with_registry!(
+        "logins_total",
+        "Sessions started by login method",
+        &["method"],
+        REGISTRY
+    )
+    .unwrap();
+    pub static ref POSTS_CREATED: IntCounter = register_int_counter_with_registry!(
+        "posts_created_total",
+        "Posts created",
+        REGISTRY
+    )
+    .unwrap();
+    pub static ref REACTIONS: IntCounterVec = register_int_counter_vec_with_registry!(
+        "reactions_total",
+        "Reactions recorded by kind",
+        &["kind"],
+        REGISTRY
+    )
+    .unwrap();
+}
+
+// lazy_static registers on first use, this makes every series show up from the first scrape
+pub fn init() {
+    lazy_static::initialize(&HTTP_REQUESTS);
+    lazy_static::initialize(&HTTP_DURATION);
+    lazy_static::initialize(&DB_POOL_SIZE);
+    lazy_static::initialize(&DB_POOL_IDLE);
+    lazy_static::initialize(&DB_POOL_MAX);
+    lazy_static::initialize(&REDIS_COMMANDS);
+    lazy_static::initialize(&REDIS_LATENCY_MS);
+    lazy_static::initialize(&REDIS_MAX_LATENCY_MS);
+    lazy_static::initialize(&REGISTRATIONS);
+    lazy_static::initialize(&LOGINS);
+    lazy_static::initialize(&POSTS_CREATED);
+    lazy_static::initialize(&REACTIONS);
+    for method in ["password", "oidc"] {
+        REGISTRATIONS.with_label_values(&[method]);
+    }
+    for method in ["password", "magic_link", "oidc"] {
+        LOGINS.with_label_values(&[method]);
+    }
+    for kind in ["like", "dislike"] {
+        REACTIONS.with_label_values(&[kind]);
+    }
+}
+
+// labels use the route pattern, "/post/:post_id" rather than every id, and
+// anything that matched no route shares one label
+pub async fn track(req: Request<Body>, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+    let started = Instant::now();
+
+    let mut response = next.run(req).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [route.as_str(), method.as_str(), status.as_str()];
+    HTTP_REQUESTS.with_label_values(&labels).inc();
+    HTTP_DURATION
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    // the trace span only knows the raw uri, telemetry::OnResponse names it after the route
+    response
+        .extensions_mut()
+        .insert(MatchedRoute { method, route });
+    response
+}
+
+fn authorized(data: &AppState, headers: &HeaderMap) -> bool {
+    let Some(token) = &data.env.metrics_token else {
+        return true;
+    };
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| given == token)
+}
+
+// GET /metrics in the Prometheus text format, behind METRICS_TOKEN when it is set
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    summary = "Prometheus metrics, behind METRICS_TOKEN when set",
+    responses(openapi::Metrics)
+)]
+pub async fn get_metrics(State(data): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if !authorized(&data, &headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    DB_POOL_SIZE.set(data.db.size() as i64);
+    DB_POOL_IDLE.set(data.db.num_idle() as i64);
+    DB_POOL_MAX.set(data.env.database_max_connections as i64);
+    let mut max_latency = 0;
+    for client in data.redis.clients() {
+        let stats = client.take_latency_metrics();
+        REDIS_COMMANDS.inc_by(stats.samples);
+        REDIS_LATENCY_MS.inc_by(stats.sum.max(0) as u64);
+        max_latency = max_latency.max(stats.max);
+    }
+    REDIS_MAX_LATENCY_MS.set(max_latency);
+
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    if encoder.encode(&REGISTRY.gather(), &mut buffer).is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    ([(header::CONTENT_TYPE, encoder.format_type().to_string())], buffer).into_response()
+}
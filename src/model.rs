@@ -7,16 +7,27 @@ pub struct User {
     pub id: Uuid,
     pub username: String,
     pub email: String,
-    pub password: String,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
     pub role: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub suspended_at: Option<DateTime<Utc>>,
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub suspension_reason: Option<String>,
+    pub password_reset_required: bool,
+    pub deactivated_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
-pub struct Register {
-    pub id: Uuid,
-    pub password: String,
+impl User {
+    // a suspension without an end date lasts until an admin lifts it
+    pub fn is_suspended(&self) -> bool {
+        match (self.suspended_at, self.suspended_until) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(_), Some(until)) => until > Utc::now(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
@@ -29,6 +40,7 @@ pub struct Profile {
     pub updated_at: DateTime<Utc>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
 pub struct Post {
     pub id: Uuid,
@@ -39,6 +51,62 @@ pub struct Post {
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
+pub struct UserIdentity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
+pub struct InviteCode {
+    pub id: Uuid,
+    pub code_prefix: String,
+    pub created_by: Option<Uuid>,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
+pub struct DataExport {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: String,
+    pub file_path: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub heartbeat_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
 //#[derive(Debug, Deserialize, sqlx::FromRow, Serialize, Clone)]
 //pub struct AlterdPost {
 //  pub id: uuid::Uuid,
@@ -0,0 +1,333 @@
+use crate::{
+    csrf::CSRF_HEADER,
+    response::{GeneralResponse, PostResponse, Status},
+    route::API_PREFIX,
+};
+use axum::{http::header, response::IntoResponse, Extension};
+use std::{collections::BTreeMap, sync::Arc};
+use utoipa::{
+    openapi::{
+        path::Operation,
+        response::Response,
+        security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme},
+        AllOfBuilder, ArrayBuilder, ContentBuilder, KnownFormat, ObjectBuilder,
+        OpenApi as Document, Ref, RefOr, ResponseBuilder, SchemaFormat, Type,
+    },
+    IntoResponses, OpenApi,
+};
+
+// the paths come from the #[utoipa::path] attributes of the handlers as
+// route::create_router mounts them, the request schemas come along with them.
+// The ones listed here are only named by the replies below
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "server",
+        description = "Every JSON endpoint answers with the GeneralResponse \
+        envelope. A request that was understood but refused, like a wrong password or a missing \
+        permission, is a 200 with status \"fail\" and the reason in message. Failures carry the \
+        request_id that is also sent in the X-Request-Id header."
+    ),
+    components(schemas(GeneralResponse, Status, PostResponse))
+)]
+pub struct ApiDoc;
+
+fn schema_ref(name: &str) -> Ref {
+    Ref::from_schema_name(name)
+}
+
+// the envelope as ApiError sends it
+fn api_error() -> ObjectBuilder {
+    ObjectBuilder::new()
+        .property("status", schema_ref("Status"))
+        .required("status")
+        .property("message", ObjectBuilder::new().schema_type(Type::String))
+        .required("message")
+        .property(
+            "data",
+            ArrayBuilder::new().description(Some("always empty")),
+        )
+        .property(
+            "request_id",
+            ObjectBuilder::new()
+                .schema_type(Type::String)
+                .description(Some("the X-Request-Id of the failed request")),
+        )
+}
+
+fn json_response(description: &str, schema: impl Into<RefOr<utoipa::openapi::Schema>>) -> Response {
+    ResponseBuilder::new()
+        .description(description)
+        .content("application/json", ContentBuilder::new().schema(Some(schema)).build())
+        .build()
+}
+
+fn replies<const N: usize>(responses: [(&str, Response); N]) -> BTreeMap<String, RefOr<Response>> {
+    responses
+        .into_iter()
+        .map(|(status, response)| (status.to_string(), response.into()))
+        .collect()
+}
+
+const ENVELOPE: &str = "success, or fail with the reason in message";
+
+fn error() -> Response {
+    json_response(
+        "status error, an unexpected failure or a body that is not valid JSON",
+        schema_ref("ApiError"),
+    )
+}
+
+// the replies the handlers name in responses(...)
+
+pub struct Envelope;
+
+impl IntoResponses for Envelope {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        replies([
+            ("200", json_response(ENVELOPE, schema_ref("GeneralResponse"))),
+            ("500", error()),
+        ])
+    }
+}
+
+pub struct Posts;
+
+impl IntoResponses for Posts {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let posts = AllOfBuilder::new()
+            .item(schema_ref("GeneralResponse"))
+            .item(ObjectBuilder::new().property(
+                "data",
+                ArrayBuilder::new().items(schema_ref("PostResponse")),
+            ));
+        replies([("200", json_response(ENVELOPE, posts)), ("500", error())])
+    }
+}
+
+pub struct Ready;
+
+impl IntoResponses for Ready {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        replies([
+            (
+                "200",
+                json_response("every dependency is up", schema_ref("GeneralResponse")),
+            ),
+            (
+                "503",
+                json_response(
+                    "shutting down, or a dependency is down",
+                    schema_ref("GeneralResponse"),
+                ),
+            ),
+        ])
+    }
+}
+
+pub struct Redirect;
+
+impl IntoResponses for Redirect {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        replies([
+            ("303", ResponseBuilder::new().description("to the frontend").build()),
+            ("200", json_response(ENVELOPE, schema_ref("GeneralResponse"))),
+            ("500", error()),
+        ])
+    }
+}
+
+pub struct Download;
+
+impl IntoResponses for Download {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let zip = ObjectBuilder::new()
+            .schema_type(Type::String)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary)));
+        replies([
+            (
+                "200",
+                ResponseBuilder::new()
+                    .description("the export")
+                    .content("application/zip", ContentBuilder::new().schema(Some(zip)).build())
+                    .build(),
+            ),
+            ("500", error()),
+        ])
+    }
+}
+
+pub struct Metrics;
+
+impl IntoResponses for Metrics {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let text = ObjectBuilder::new().schema_type(Type::String);
+        replies([
+            (
+                "200",
+                ResponseBuilder::new()
+                    .description("the Prometheus text format")
+                    .content("text/plain", ContentBuilder::new().schema(Some(text)).build())
+                    .build(),
+            ),
+            ("401", ResponseBuilder::new().description("a wrong METRICS_TOKEN").build()),
+        ])
+    }
+}
+
+pub struct Published;
+
+impl IntoResponses for Published {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        replies([("200", json_response("an OpenAPI 3 document", ObjectBuilder::new()))])
+    }
+}
+
+// the handlers declare a scope on api_token and a permission on session, the
+// description spells out what that means for a caller
+fn describe_access(method: &str, operation: &mut Operation) {
+    let requirements = operation
+        .security
+        .iter()
+        .flatten()
+        .filter_map(|requirement| serde_json::to_value(requirement).ok())
+        .filter_map(|value| value.as_object().cloned())
+        .flatten()
+        .collect::<Vec<_>>();
+    if requirements.is_empty() {
+        return;
+    }
+
+    let mut notes = Vec::new();
+    for (scheme, values) in &requirements {
+        for value in values.as_array().into_iter().flatten().filter_map(|v| v.as_str()) {
+            match scheme.as_str() {
+                "api_token" => notes.push(format!("An api token needs the {} scope.", value)),
+                _ => notes.push(format!(
+                    "The session user's role needs the {} permission.",
+                    value
+                )),
+            }
+        }
+    }
+    if method != "get" {
+        notes.push(format!(
+            "With a session cookie the {} header must hold the token from {}/auth/csrf.",
+            CSRF_HEADER, API_PREFIX
+        ));
+    }
+    if !notes.is_empty() {
+        let notes = notes.join(" ");
+        operation.description = Some(match operation.description.take() {
+            Some(description) => format!("{} {}", description, notes),
+            None => notes,
+        });
+    }
+}
+
+// the document split off the router, with what the attributes can't say
+pub fn finish(mut doc: Document) -> Document {
+    for item in doc.paths.paths.values_mut() {
+        let operations = [
+            ("get", &mut item.get),
+            ("post", &mut item.post),
+            ("put", &mut item.put),
+            ("patch", &mut item.patch),
+            ("delete", &mut item.delete),
+        ];
+        for (method, operation) in operations {
+            if let Some(operation) = operation {
+                describe_access(method, operation);
+            }
+        }
+    }
+
+    let components = doc.components.get_or_insert_with(Default::default);
+    components
+        .schemas
+        .insert("ApiError".to_string(), api_error().into());
+    components.add_security_scheme(
+        "session",
+        SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+            "id",
+            "the tower-sessions cookie set by a login",
+        ))),
+    );
+    components.add_security_scheme(
+        "api_token",
+        SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+    );
+    doc
+}
+
+// the rendered document, create_router puts it in an Extension
+#[derive(Clone)]
+pub struct DocumentJson(pub Arc<str>);
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "operations",
+    summary = "This document",
+    responses(Published)
+)]
+pub async fn get_openapi(Extension(DocumentJson(document)): Extension<DocumentJson>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], document.to_string())
+}
+
+const REDOC_SCRIPT: &str = "https://cdn.redoc.ly/redoc/v2.2.0/bundles/redoc.standalone.js";
+
+// Redoc loads the spec itself, so the page needs no inline script. Its styles
+// are injected at runtime and search runs in a worker made from a blob
+const DOCS_POLICY: &str = "default-src 'none'; script-src https://cdn.redoc.ly; \
+    style-src 'unsafe-inline'; img-src 'self' data:; font-src data:; connect-src 'self'; \
+    worker-src blob:; base-uri 'none'; form-action 'none'; frame-ancestors 'none'";
+
+// GET /docs, the page sets its own CSP and the global default does not replace it
+pub async fn get_docs() -> impl IntoResponse {
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n  <head>\n    <title>server api</title>\n    \
+        <meta charset=\"utf-8\"/>\n    \
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n  </head>\n  \
+        <body>\n    <redoc spec-url=\"/openapi.json\"></redoc>\n    \
+        <script src=\"{}\"></script>\n  </body>\n</html>\n",
+        REDOC_SCRIPT
+    );
+    (
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CONTENT_SECURITY_POLICY, DOCS_POLICY),
+        ],
+        page,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utoipa::openapi::{path::OperationBuilder, security::SecurityRequirement};
+
+    #[test]
+    fn describes_scopes_permissions_and_csrf() {
+        let mut operation = OperationBuilder::new()
+            .security(SecurityRequirement::new("session", Vec::<String>::new()))
+            .security(SecurityRequirement::new("api_token", ["posts:write"]))
+            .build();
+        describe_access("post", &mut operation);
+        let description = operation.description.unwrap();
+        assert!(description.contains("the posts:write scope"));
+        assert!(description.contains(CSRF_HEADER));
+
+        let mut operation = OperationBuilder::new()
+            .security(SecurityRequirement::new("session", ["audit:read"]))
+            .build();
+        describe_access("get", &mut operation);
+        let description = operation.description.unwrap();
+        assert!(description.contains("the audit:read permission"));
+        assert!(!description.contains(CSRF_HEADER));
+
+        let mut operation = OperationBuilder::new().build();
+        describe_access("post", &mut operation);
+        assert!(operation.description.is_none());
+    }
+}
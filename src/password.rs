@@ -0,0 +1,251 @@
+use crate::{config::Config, response::ApiError};
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, KeyId, Params, ParamsBuilder, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+// peppered hashes carry a short key id derived from the pepper, so we know
+// which secret to verify with and when a hash predates the current pepper
+fn pepper_id(pepper: &str) -> [u8; 4] {
+    let digest = Sha256::digest(pepper.as_bytes());
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+fn params(config: &Config) -> Params {
+    let mut builder = ParamsBuilder::new();
+    builder
+        .m_cost(config.argon2_memory_kib)
+        .t_cost(config.argon2_iterations)
+        .p_cost(config.argon2_parallelism);
+    if let Some(pepper) = &config.password_pepper {
+        builder.keyid(KeyId::new(&pepper_id(pepper)).expect("key id fits"));
+    }
+    builder
+        .build()
+        .expect("argon2 parameters are checked in Config::init")
+}
+
+fn hasher(pepper: Option<&str>, params: Params) -> Argon2<'_> {
+    match pepper {
+        Some(pepper) => Argon2::new_with_secret(
+            pepper.as_bytes(),
+            Algorithm::Argon2id,
+            Version::V0x13,
+            params,
+        )
+        .expect("pepper fits argon2 secret"),
+        None => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+    }
+}
+
+pub fn hash(config: &Config, password: &str) -> Result<String, ApiError> {
+    let salt = SaltString::generate(&mut OsRng);
+    hasher(config.password_pepper.as_deref(), params(config))
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| ApiError::InternalServerError)
+}
+
+pub fn verify(config: &Config, hash: &str, password: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+    // hashes from before the pepper was introduced have no key id
+    let peppered = Params::try_from(&parsed_hash)
+        .map(|params| !params.keyid().is_empty())
+        .unwrap_or(false);
+    let pepper = config.password_pepper.as_deref().filter(|_| peppered);
+    hasher(pepper, Params::default())
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok()
+}
+
+// true when the stored hash was made with other parameters or pepper than we use now
+pub fn needs_rehash(config: &Config, hash: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(hash) else {
+        return true;
+    };
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    let Ok(stored) = Params::try_from(&parsed_hash) else {
+        return true;
+    };
+    let current = params(config);
+    stored.m_cost() != current.m_cost()
+        || stored.t_cost() != current.t_cost()
+        || stored.p_cost() != current.p_cost()
+        || stored.keyid() != current.keyid()
+}
+
+// `cargo run --release -- bench-argon2 [target_ms]` prints parameters that take about
+// target_ms per hash on this host, pick the largest memory the host can afford
+pub fn benchmark(target: Duration) {
+    println!(
+        "Benchmarking argon2id, target {} ms per hash",
+        target.as_millis()
+    );
+    let mut suggestion: Option<(u32, u32)> = None;
+    for memory_kib in [19 * 1024, 46 * 1024, 64 * 1024, 128 * 1024, 256 * 1024] {
+        let Ok(params) = Params::new(memory_kib, 1, 1, None) else {
+            continue;
+        };
+        let started = Instant::now();
+        let salt = SaltString::generate(&mut OsRng);
+        if hasher(None, params)
+            .hash_password(b"benchmark password", &salt)
+            .is_err()
+        {
+            println!("  m={} KiB: failed, not enough memory", memory_kib);
+            break;
+        }
+        let single = started.elapsed();
+        let iterations = (target.as_secs_f64() / single.as_secs_f64()).floor() as u32;
+        println!(
+            "  m={} KiB: {} ms per iteration, {} iterations fit",
+            memory_kib,
+            single.as_millis(),
+            iterations
+        );
+        if iterations == 0 {
+            break;
+        }
+        if iterations >= 2 || suggestion.is_none() {
+            suggestion = Some((memory_kib, iterations.max(1)));
+        }
+    }
+
+    match suggestion {
+        Some((memory_kib, iterations)) => {
+            println!("Suggested settings:");
+            println!("ARGON2_MEMORY_KIB={}", memory_kib);
+            println!("ARGON2_ITERATIONS={}", iterations);
+            println!("ARGON2_PARALLELISM=1");
+        }
+        None => println!("Even the smallest setting is slower than the target, raise it"),
+    }
+}
+
+// the Pwned Passwords range files, one per 5 hex digit SHA-1 prefix named
+// like 21BD1.txt and holding the remaining 35 digits of every leaked hash in
+// that range as "SUFFIX:COUNT" lines, the layout `haveibeenpwned-downloader -s false`
+// writes. A check reads the one range its password falls in, the same k-anonymity
+// lookup as the online api, so the full set never has to fit in memory
+#[derive(Default)]
+pub struct BreachedPasswords {
+    dir: Option<PathBuf>,
+}
+
+impl BreachedPasswords {
+    pub fn open(dir: &str) -> std::io::Result<BreachedPasswords> {
+        let dir = PathBuf::from(dir);
+        if !std::fs::metadata(&dir)?.is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "not a directory of range files",
+            ));
+        }
+        Ok(BreachedPasswords { dir: Some(dir) })
+    }
+
+    pub async fn contains(&self, password: &str) -> std::io::Result<bool> {
+        let Some(dir) = &self.dir else {
+            return Ok(false);
+        };
+        let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = digest.split_at(5);
+        let range = match tokio::fs::read_to_string(dir.join(format!("{}.txt", prefix))).await {
+            Ok(range) => range,
+            // nothing leaked in this range
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        Ok(range.lines().any(|line| {
+            line.split(':')
+                .next()
+                .is_some_and(|leaked| leaked.trim().eq_ignore_ascii_case(suffix))
+        }))
+    }
+}
+
+// applied wherever a user picks a new password
+pub async fn check_policy(
+    config: &Config,
+    breached: &BreachedPasswords,
+    password: &str,
+    username: &str,
+    email: &str,
+) -> Result<(), ApiError> {
+    if password.chars().count() < config.password_min_length {
+        return Err(ApiError::Fail(format!(
+            "password must be at least {} characters",
+            config.password_min_length
+        )));
+    }
+
+    let lowered = password.to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+    for personal in [username, email, local_part] {
+        let personal = personal.to_lowercase();
+        if personal.chars().count() >= 3 && lowered.contains(&personal) {
+            return Err(ApiError::Fail(
+                "password can not contain your username or email".to_string(),
+            ));
+        }
+    }
+
+    let entropy = zxcvbn::zxcvbn(password, &[username, email, local_part]);
+    if u8::from(entropy.score()) < config.password_min_score {
+        let hint = entropy
+            .feedback()
+            .and_then(|feedback| feedback.warning())
+            .map(|warning| format!(", {}", warning.to_string().to_lowercase()))
+            .unwrap_or_default();
+        return Err(ApiError::Fail(format!("password is too weak{}", hint)));
+    }
+
+    let is_breached = breached.contains(password).await.map_err(|err| {
+        tracing::error!(error = %err, "failed to read breached password range");
+        ApiError::InternalServerError
+    })?;
+    if is_breached {
+        return Err(ApiError::Fail(
+            "password appears in a known data breach, choose another".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn finds_passwords_in_their_range_file() {
+        let dir = std::env::temp_dir().join(format!("breached-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        std::fs::write(
+            dir.join("5BAA6.txt"),
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n",
+        )
+        .unwrap();
+        let breached = BreachedPasswords::open(dir.to_str().unwrap()).unwrap();
+
+        assert!(breached.contains("password").await.unwrap());
+        // a range without a file
+        assert!(!breached.contains("correct horse battery staple").await.unwrap());
+        assert!(!BreachedPasswords::default().contains("password").await.unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    accounts,
+    client::ClientInfo,
+    magic_link::CLAIM_LINK,
+    model::User,
+    openapi,
+    response::{ApiError, AppJson, GeneralResponse, Status},
+    schema::PasswordResetSchema,
+    signing,
+    tokens::hash_token,
+    AppState,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{extract::State, response::IntoResponse, Json};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use reqwest::Url;
+use std::sync::Arc;
+use tower_sessions_redis_store::fred::prelude::*;
+use uuid::Uuid;
+
+const RESET_TTL_SECS: i64 = 24 * 60 * 60;
+
+fn reset_key(token: &str) -> String {
+    format!("password_reset:{}", hash_token(token))
+}
+
+fn reset_payload(token: &str, expires: i64) -> String {
+    format!("password-reset:{}:{}", token, expires)
+}
+
+// mails a link to PASSWORD_RESET_URL, the page there posts the new password
+// with the token to /auth/password-reset. The link works once, a newer one
+// does not cancel it
+pub async fn send_reset_link(data: &AppState, user: &User) -> Result<(), ApiError> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+    data.redis
+        .set::<(), _, _>(
+            reset_key(&token),
+            user.id.to_string(),
+            Some(Expiration::EX(RESET_TTL_SECS)),
+            None,
+            false,
+        )
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+    let expires = Utc::now().timestamp() + RESET_TTL_SECS;
+    let sig = signing::sign(&data.env.app_secret, &reset_payload(&token, expires));
+    let mut link = Url::parse(&data.env.password_reset_url)
+        .map_err(|_| ApiError::InternalServerError)?;
+    link.query_pairs_mut()
+        .append_pair("token", &token)
+        .append_pair("expires", &expires.to_string())
+        .append_pair("sig", &sig);
+    data.mailer
+        .send(
+            &user.email,
+            "Choose a new password",
+            &format!(
+                "Hi {},\n\nan administrator asked you to choose a new password. You can not log in with the old one anymore. This link lets you pick a new one for the next 24 hours:\n\n{}\n",
+                user.username, link
+            ),
+        )
+        .await
+}
+
+// a password that would be refused must not use up the link, so the checks run
+// before the claim
+async fn claim_link(
+    data: &AppState,
+    user: &User,
+    token: &str,
+    stored: String,
+    new_password: &str,
+) -> Result<(), ApiError> {
+    accounts::check_new_password(&data.env, &data.breached_passwords, user, new_password).await?;
+    let claimed: i64 = data
+        .redis
+        .eval(CLAIM_LINK, reset_key(token), stored)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if claimed != 1 {
+        return Err(ApiError::Fail("reset link already used".to_string()));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password-reset",
+    tag = "auth",
+    summary = "Choose a new password with an emailed reset link",
+    request_body = PasswordResetSchema,
+    responses(openapi::Envelope)
+)]
+pub async fn reset_password(
+    client: ClientInfo,
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<PasswordResetSchema>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !signing::verify(
+        &data.env.app_secret,
+        &reset_payload(&body.token, body.expires),
+        &body.sig,
+    ) {
+        return Err(ApiError::Fail("invalid reset link".to_string()));
+    }
+    if body.expires < Utc::now().timestamp() {
+        return Err(ApiError::Fail("reset link expired".to_string()));
+    }
+
+    let stored: Option<String> = data
+        .redis
+        .get(reset_key(&body.token))
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    let stored = stored.ok_or_else(|| ApiError::Fail("reset link already used".to_string()))?;
+    let user_id: Uuid = stored.parse().map_err(|_| ApiError::InternalServerError)?;
+    let user = sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?
+        .ok_or_else(|| ApiError::Fail("user does not exist".to_string()))?;
+
+    claim_link(&data, &user, &body.token, stored, &body.new_password).await?;
+    accounts::store_password(&data, &client, &user, &body.new_password, "reset_link").await?;
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Password changed, log in with the new one".to_string(),
+        data: None,
+    };
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, mailer::LogMailer, password::BreachedPasswords};
+    use sqlx::postgres::PgPoolOptions;
+    use std::sync::atomic::AtomicBool;
+    use tower_sessions_redis_store::fred::mocks::{MockCommand, Mocks, SimpleMap};
+
+    // GET and SET from SimpleMap, EVAL runs CLAIM_LINK as the script would
+    #[derive(Debug)]
+    struct Links(SimpleMap);
+
+    impl Mocks for Links {
+        fn process_command(&self, command: MockCommand) -> Result<RedisValue, RedisError> {
+            match &*command.cmd {
+                "GET" => self.0.get(command.args),
+                "SET" => self.0.set(command.args),
+                "EVAL" => {
+                    let (key, expected) = (command.args[2].clone(), command.args[3].clone());
+                    if self.0.get(vec![key.clone()])? == expected {
+                        self.0.del(vec![key])
+                    } else {
+                        Ok(0.into())
+                    }
+                }
+                _ => Err(RedisError::new(RedisErrorKind::Unknown, "not mocked")),
+            }
+        }
+    }
+
+    async fn state() -> AppState {
+        std::env::set_var("DATABASE_URL", "postgresql://test@localhost:1/test");
+        std::env::set_var("APP_SECRET", "test");
+        let env = Config::init().unwrap();
+        let redis = RedisPool::new(
+            RedisConfig {
+                mocks: Some(Arc::new(Links(SimpleMap::new()))),
+                ..Default::default()
+            },
+            None,
+            None,
+            None,
+            1,
+        )
+        .unwrap();
+        redis.init().await.unwrap();
+        AppState {
+            db: PgPoolOptions::new().connect_lazy(&env.database_url).unwrap(),
+            redis,
+            http: reqwest::Client::new(),
+            mailer: Arc::new(LogMailer),
+            breached_passwords: BreachedPasswords::default(),
+            recorder: None,
+            draining: AtomicBool::new(false),
+            env,
+        }
+    }
+
+    #[tokio::test]
+    async fn a_refused_password_leaves_the_link_usable() {
+        let data = state().await;
+        let old_password = "amber-kettle-orchard-17";
+        let user = User {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password: crate::password::hash(&data.env, old_password).ok(),
+            role: "user".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            suspended_at: None,
+            suspended_until: None,
+            suspension_reason: None,
+            password_reset_required: true,
+            deactivated_at: None,
+        };
+        let stored = user.id.to_string();
+        data.redis
+            .set::<(), _, _>(reset_key("token"), stored.clone(), None, None, false)
+            .await
+            .unwrap();
+
+        for refused in [old_password, "short"] {
+            let result = claim_link(&data, &user, "token", stored.clone(), refused).await;
+            assert!(matches!(result, Err(ApiError::Fail(_))));
+            let left: Option<String> = data.redis.get(reset_key("token")).await.unwrap();
+            assert_eq!(left.as_deref(), Some(stored.as_str()));
+        }
+
+        let claimed =
+            claim_link(&data, &user, "token", stored.clone(), "copper-lantern-meadow-42").await;
+        assert!(claimed.is_ok());
+        let left: Option<String> = data.redis.get(reset_key("token")).await.unwrap();
+        assert!(left.is_none());
+        let again = claim_link(&data, &user, "token", stored, "copper-lantern-meadow-42").await;
+        assert!(matches!(again, Err(ApiError::Fail(_))));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{
+    openapi, rate_limit,
+    response::{ApiError, GeneralResponse, Status},
+    schema::{ChallengeQuery, PowSolution},
+    signing, AppState,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tower_sessions_redis_store::fred::prelude::*;
+
+const CHALLENGE_TTL_SECS: i64 = 5 * 60;
+const VOLUME_WINDOW_SECS: i64 = 60;
+const PURPOSES: [&str; 2] = ["register", "post"];
+
+// hashcash style: find a nonce so that sha256("{challenge}:{nonce}") starts
+// with `difficulty` zero bits, each extra bit doubles the expected work
+fn leading_zero_bits(digest: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in digest {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
+
+fn challenge_payload(purpose: &str, challenge: &str, difficulty: u32, expires: i64) -> String {
+    format!("pow:{}:{}:{}:{}", purpose, challenge, difficulty, expires)
+}
+
+fn volume_key(purpose: &str) -> String {
+    format!("pow_volume:{}", purpose)
+}
+
+// every doubling of the per minute volume of accepted solutions above the
+// threshold adds a bit. Only spent solutions count, asking for challenges
+// costs nothing and must not raise the difficulty for everyone else
+async fn current_difficulty(data: &AppState, purpose: &str) -> Result<u32, ApiError> {
+    let volume = rate_limit::peek(&data.redis, &volume_key(purpose)).await?;
+    let mut difficulty = data.env.pow_base_difficulty;
+    let mut threshold = data.env.pow_volume_threshold;
+    while volume > threshold && difficulty < data.env.pow_max_difficulty {
+        difficulty += 1;
+        threshold *= 2;
+    }
+    Ok(difficulty)
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/challenge",
+    tag = "auth",
+    summary = "A proof of work challenge",
+    params(ChallengeQuery),
+    responses(openapi::Envelope)
+)]
+pub async fn get_challenge(
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<ChallengeQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !PURPOSES.contains(&query.purpose.as_str()) {
+        return Err(ApiError::Fail("purpose must be register or post".to_string()));
+    }
+    let difficulty = current_difficulty(&data, &query.purpose).await?;
+
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    let challenge = URL_SAFE_NO_PAD.encode(bytes);
+    let expires = Utc::now().timestamp() + CHALLENGE_TTL_SECS;
+    let sig = signing::sign(
+        &data.env.app_secret,
+        &challenge_payload(&query.purpose, &challenge, difficulty, expires),
+    );
+
+    let response: GeneralResponse = GeneralResponse {
+        status: Status::Success,
+        message: "Challenge issued".to_string(),
+        data: Some(json!({
+            "challenge": challenge,
+            "difficulty": difficulty,
+            "expires": expires,
+            "sig": sig,
+            "algorithm": "sha256",
+        })),
+    };
+    Ok(Json(response))
+}
+
+// checks the signature, expiry and work, then burns the challenge so a
+// solution can only be spent once
+pub async fn verify(data: &AppState, purpose: &str, solution: Option<&PowSolution>) -> Result<(), ApiError> {
+    let solution =
+        solution.ok_or_else(|| ApiError::Fail("a proof of work solution is required".to_string()))?;
+    if !signing::verify(
+        &data.env.app_secret,
+        &challenge_payload(purpose, &solution.challenge, solution.difficulty, solution.expires),
+        &solution.sig,
+    ) {
+        return Err(ApiError::Fail("invalid proof of work challenge".to_string()));
+    }
+    if solution.expires < Utc::now().timestamp() {
+        return Err(ApiError::Fail("proof of work challenge expired".to_string()));
+    }
+    let digest = Sha256::digest(format!("{}:{}", solution.challenge, solution.nonce).as_bytes());
+    if leading_zero_bits(&digest) < solution.difficulty {
+        return Err(ApiError::Fail("proof of work solution is wrong".to_string()));
+    }
+
+    let fresh: Option<String> = data
+        .redis
+        .set(
+            format!("pow_used:{}", solution.challenge),
+            "1",
+            Some(Expiration::EX(CHALLENGE_TTL_SECS)),
+            Some(SetOptions::NX),
+            false,
+        )
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if fresh.is_none() {
+        return Err(ApiError::Fail("proof of work challenge already used".to_string()));
+    }
+    rate_limit::count(&data.redis, &volume_key(purpose), VOLUME_WINDOW_SECS).await?;
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use crate::response::ApiError;
+use tower_sessions_redis_store::fred::prelude::*;
+
+// fixed window counter, returns false once the key went over the limit
+pub async fn hit(
+    redis: &RedisPool,
+    key: &str,
+    limit: i64,
+    window_secs: i64,
+) -> Result<bool, ApiError> {
+    Ok(count(redis, key, window_secs).await? <= limit)
+}
+
+// counts a hit and returns how many the current window has seen
+pub async fn count(redis: &RedisPool, key: &str, window_secs: i64) -> Result<i64, ApiError> {
+    let key = format!("rate_limit:{}", key);
+    let count: i64 = redis
+        .incr(&key)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if count == 1 {
+        redis
+            .expire::<(), _>(&key, window_secs)
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
+    }
+    Ok(count)
+}
+
+// the current window's count without adding to it
+pub async fn peek(redis: &RedisPool, key: &str) -> Result<i64, ApiError> {
+    let count: Option<i64> = redis
+        .get(format!("rate_limit:{}", key))
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    Ok(count.unwrap_or(0))
+}
@@ -0,0 +1,115 @@
+use crate::response::ApiError;
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, Request, State},
+    http::request::Parts,
+    middleware::Next,
+    response::IntoResponse,
+};
+
+// users.role holds one of these, anything unknown is treated as a plain user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    DeleteAnyPost,
+    ManageUsers,
+    ViewAuditLog,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::DeleteAnyPost => "posts:delete_any",
+            Permission::ManageUsers => "users:manage",
+            Permission::ViewAuditLog => "audit:read",
+        }
+    }
+}
+
+impl Role {
+    pub fn parse(role: &str) -> Role {
+        Role::from_name(role).unwrap_or(Role::User)
+    }
+
+    pub fn from_name(role: &str) -> Option<Role> {
+        match role {
+            "user" => Some(Role::User),
+            "moderator" => Some(Role::Moderator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::User => &[],
+            Role::Moderator => &[Permission::DeleteAnyPost],
+            Role::Admin => &[
+                Permission::DeleteAnyPost,
+                Permission::ManageUsers,
+                Permission::ViewAuditLog,
+            ],
+        }
+    }
+}
+
+// inserted by session_auth::auth, handlers take it as an extractor
+#[derive(Debug, Clone, Copy)]
+pub struct Permissions {
+    pub role: Role,
+}
+
+impl Permissions {
+    pub fn for_role(role: &str) -> Self {
+        Self {
+            role: Role::parse(role),
+        }
+    }
+
+    pub fn has(&self, permission: Permission) -> bool {
+        self.role.permissions().contains(&permission)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Permissions
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Permissions>()
+            .copied()
+            .ok_or_else(|| ApiError::Fail("user unauthorized".to_string()))
+    }
+}
+
+// route layer, runs inside session_auth::auth
+pub async fn require_permission(
+    State(permission): State<Permission>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    match req.extensions().get::<Permissions>() {
+        Some(permissions) if permissions.has(permission) => Ok(next.run(req).await),
+        _ => Err(ApiError::Fail("not authorized".to_string())),
+    }
+}
@@ -0,0 +1,311 @@
+use crate::AppState;
+use axum::{
+    body::{to_bytes, Body},
+    extract::State,
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+
+pub const REDACTED: &str = "[redacted]";
+
+// same as the default limit of the Json extractor
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// headers that carry credentials, replay brings its own
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "proxy-authorization",
+    crate::csrf::CSRF_HEADER,
+];
+
+// fields that hold the user's password, replay fills them back in
+pub const PASSWORD_FIELDS: &[&str] = &["password", "new_password", "current_password"];
+
+// the other body and query fields that are secrets somewhere in the api
+const REDACTED_FIELDS: &[&str] = &[
+    "token",
+    "csrf_token",
+    "invite_code",
+    "code",
+    "state",
+    "sig",
+    "nonce",
+    "client_secret",
+];
+
+// one line of the record file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    pub time: DateTime<Utc>,
+    pub method: String,
+    pub uri: String,
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub headers: BTreeMap<String, String>,
+    // only json bodies are kept
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<Value>,
+}
+
+// lines go through a channel to one writer task so requests never wait on the file
+#[derive(Clone)]
+pub struct Recorder {
+    lines: mpsc::UnboundedSender<String>,
+}
+
+impl Recorder {
+    pub async fn open(path: &str) -> std::io::Result<Recorder> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let (lines, mut receiver) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            while let Some(line) = receiver.recv().await {
+                // flushed line by line so a shutdown loses nothing
+                if let Err(err) = async {
+                    file.write_all(line.as_bytes()).await?;
+                    file.flush().await
+                }
+                .await
+                {
+                    tracing::error!("failed to record a request: {:?}", err);
+                }
+            }
+        });
+        Ok(Recorder { lines })
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    PASSWORD_FIELDS.contains(&key.as_str()) || REDACTED_FIELDS.contains(&key.as_str())
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if is_secret(key) && !field.is_null() {
+                    *field = Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        // signed links like download_url carry their secret in the query
+        Value::String(text) => {
+            if let Some(url) = redact_url(text) {
+                *text = url;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if is_secret(key) => {
+                format!("{}={}", key, REDACTED)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+// "https://app.example.com/x?sig=abc" -> "https://app.example.com/x?sig=[redacted]",
+// None for anything that is not an absolute url with a query
+fn redact_url(text: &str) -> Option<String> {
+    if !(text.starts_with("http://") || text.starts_with("https://")) {
+        return None;
+    }
+    let (base, query) = text.split_once('?')?;
+    Some(format!("{}?{}", base, redact_query(query)))
+}
+
+fn redact_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+// "/auth/magic-link/verify?token=abc&expires=1" -> "/auth/magic-link/verify?token=[redacted]&expires=1"
+pub fn redact_uri(uri: &axum::http::Uri) -> String {
+    match uri.query() {
+        Some(query) => format!("{}?{}", uri.path(), redact_query(query)),
+        None => uri.path().to_string(),
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+fn json_body(bytes: &[u8]) -> Option<Value> {
+    let mut body = serde_json::from_slice::<Value>(bytes).ok()?;
+    redact_value(&mut body);
+    Some(body)
+}
+
+// RECORD_REQUESTS=true appends each request and response to RECORD_FILE as a json
+// line with credentials redacted, see `server replay`
+pub async fn record(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(recorder) = data.recorder.clone() else {
+        return next.run(req).await;
+    };
+
+    let started = Instant::now();
+    let time = Utc::now();
+    let method = req.method().to_string();
+    let uri = redact_uri(req.uri());
+    let request_headers = redact_headers(req.headers());
+    let request_is_json = is_json(req.headers());
+    let (parts, body) = req.into_parts();
+    let bytes = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let request_body = if request_is_json { json_body(&bytes) } else { None };
+
+    let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+
+    // file downloads are streamed through untouched
+    let (parts, body) = response.into_parts();
+    let (response, response_body) = if is_json(&parts.headers) {
+        match to_bytes(body, MAX_BODY_BYTES).await {
+            Ok(bytes) => {
+                let recorded = json_body(&bytes);
+                (Response::from_parts(parts, Body::from(bytes)), recorded)
+            }
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    } else {
+        (Response::from_parts(parts, body), None)
+    };
+
+    let exchange = Exchange {
+        time,
+        method,
+        uri,
+        request: RecordedRequest {
+            headers: request_headers,
+            body: request_body,
+        },
+        response: RecordedResponse {
+            status: response.status().as_u16(),
+            headers: redact_headers(response.headers()),
+            body: response_body,
+        },
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    };
+    if let Ok(line) = serde_json::to_string(&exchange) {
+        let _ = recorder.lines.send(line + "\n");
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_secret_fields_at_any_depth() {
+        let mut body = json!({
+            "username": "kakky",
+            "password": "hunter22",
+            "pow": { "nonce": "42", "difficulty": 18 },
+            "tokens": [{ "token": "pat_abc", "name": "ci" }],
+            "new_password": null,
+            "current_password": "hunter22",
+        });
+        redact_value(&mut body);
+        assert_eq!(
+            body,
+            json!({
+                "username": "kakky",
+                "password": REDACTED,
+                "pow": { "nonce": REDACTED, "difficulty": 18 },
+                "tokens": [{ "token": REDACTED, "name": "ci" }],
+                "new_password": null,
+                "current_password": REDACTED,
+            })
+        );
+    }
+
+    #[test]
+    fn redacts_signed_urls_in_values() {
+        let mut body = json!({
+            "data": [{
+                "download_url": "https://app.example.com/v1/user/export/1/download?expires=9&sig=abc",
+                "status": "ready",
+            }],
+            "message": "see https://example.com/docs",
+        });
+        redact_value(&mut body);
+        assert_eq!(
+            body["data"][0]["download_url"],
+            "https://app.example.com/v1/user/export/1/download?expires=9&sig=[redacted]"
+        );
+        assert_eq!(body["message"], "see https://example.com/docs");
+    }
+
+    #[test]
+    fn redacts_query_secrets_in_uris() {
+        let uri = "/v1/auth/magic-link/verify?token=abc&expires=1&sig=def"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            redact_uri(&uri),
+            "/v1/auth/magic-link/verify?token=[redacted]&expires=1&sig=[redacted]"
+        );
+
+        let uri = "/v1/auth/oidc/local/callback?Code=abc&state=def&flag".parse().unwrap();
+        assert_eq!(
+            redact_uri(&uri),
+            "/v1/auth/oidc/local/callback?Code=[redacted]&state=[redacted]&flag"
+        );
+
+        let uri = "/v1/post/get_all".parse().unwrap();
+        assert_eq!(redact_uri(&uri), "/v1/post/get_all");
+    }
+}
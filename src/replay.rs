@@ -0,0 +1,298 @@
+use crate::{
+    cli::fail,
+    csrf::CSRF_HEADER,
+    recorder::{Exchange, PASSWORD_FIELDS, REDACTED},
+};
+use reqwest::{redirect::Policy, Method};
+use serde_json::Value;
+use std::{path::PathBuf, time::Instant};
+
+// headers the replaying client sets itself
+const SKIPPED_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "cookie",
+    "authorization",
+    "proxy-authorization",
+    CSRF_HEADER,
+];
+
+struct Outcome {
+    mismatches: Vec<String>,
+    latencies_ms: Vec<u64>,
+}
+
+// null stands in for a missing optional value, so it matches anything
+fn same_shape(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Null, _) | (_, Value::Null) => true,
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().all(|(key, value)| {
+                    actual.get(key).is_some_and(|other| same_shape(value, other))
+                })
+        }
+        (Value::Array(expected), Value::Array(actual)) => match (expected.first(), actual.first()) {
+            (Some(expected), Some(actual)) => same_shape(expected, actual),
+            _ => true,
+        },
+        (Value::Bool(_), Value::Bool(_))
+        | (Value::Number(_), Value::Number(_))
+        | (Value::String(_), Value::String(_)) => true,
+        _ => false,
+    }
+}
+
+// the recording has no passwords, put the one given on the command line back
+fn fill_passwords(value: &mut Value, password: &str) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if PASSWORD_FIELDS.contains(&key.as_str()) && field.as_str() == Some(REDACTED) {
+                    *field = Value::String(password.to_string());
+                } else {
+                    fill_passwords(field, password);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| fill_passwords(item, password)),
+        _ => {}
+    }
+}
+
+fn compare(exchange: &Exchange, status: u16, body: Option<&Value>) -> Option<String> {
+    if exchange.response.status != status {
+        return Some(format!("status {} != {}", exchange.response.status, status));
+    }
+    let (Some(expected), Some(actual)) = (&exchange.response.body, body) else {
+        return None;
+    };
+    // a fail envelope still comes back as 200, so compare it as well
+    let expected_status = expected.get("status").and_then(Value::as_str);
+    let actual_status = actual.get("status").and_then(Value::as_str);
+    if expected_status != actual_status {
+        return Some(format!(
+            "envelope {} != {}",
+            expected_status.unwrap_or("none"),
+            actual_status.unwrap_or("none")
+        ));
+    }
+    if !same_shape(expected, actual) {
+        return Some("body shape differs".to_string());
+    }
+    None
+}
+
+// one client replays the whole file in order with its own cookie jar, so the
+// session cookie and csrf token come from the target instead of the recording
+async fn replay_client(
+    client_index: usize,
+    target: String,
+    exchanges: std::sync::Arc<Vec<(usize, Exchange)>>,
+    password: Option<String>,
+    token: Option<String>,
+) -> Outcome {
+    let client = reqwest::Client::builder()
+        .cookie_store(true)
+        .redirect(Policy::none())
+        .build()
+        .unwrap();
+    let mut csrf_token: Option<String> = None;
+    let mut outcome = Outcome {
+        mismatches: Vec::new(),
+        latencies_ms: Vec::with_capacity(exchanges.len()),
+    };
+
+    for (line, exchange) in exchanges.iter() {
+        let label = format!("client {} line {} {} {}", client_index, line, exchange.method, exchange.uri);
+        let method = match Method::from_bytes(exchange.method.as_bytes()) {
+            Ok(method) => method,
+            Err(_) => {
+                outcome.mismatches.push(format!("{}: unknown method", label));
+                continue;
+            }
+        };
+        let mut request = client.request(method, format!("{}{}", target, exchange.uri));
+        for (name, value) in &exchange.request.headers {
+            if !SKIPPED_HEADERS.contains(&name.as_str()) {
+                request = request.header(name, value);
+            }
+        }
+        if exchange.request.headers.contains_key("authorization") {
+            if let Some(token) = &token {
+                request = request.bearer_auth(token);
+            }
+        }
+        if exchange.request.headers.contains_key(CSRF_HEADER) {
+            if let Some(csrf_token) = &csrf_token {
+                request = request.header(CSRF_HEADER, csrf_token);
+            }
+        }
+        if let Some(body) = &exchange.request.body {
+            let mut body = body.clone();
+            if let Some(password) = &password {
+                fill_passwords(&mut body, password);
+            }
+            request = request.json(&body);
+        }
+
+        let started = Instant::now();
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(err) => {
+                outcome.mismatches.push(format!("{}: request failed: {}", label, err));
+                continue;
+            }
+        };
+        let status = response.status().as_u16();
+        let body = response.json::<Value>().await.ok();
+        outcome.latencies_ms.push(started.elapsed().as_millis() as u64);
+
+        if let Some(token) = body
+            .as_ref()
+            .and_then(|body| body.pointer("/data/csrf_token"))
+            .and_then(Value::as_str)
+        {
+            csrf_token = Some(token.to_string());
+        }
+        if let Some(mismatch) = compare(exchange, status, body.as_ref()) {
+            outcome.mismatches.push(format!("{}: {}", label, mismatch));
+        }
+    }
+    outcome
+}
+
+pub async fn replay(
+    file: PathBuf,
+    target: String,
+    password: Option<String>,
+    token: Option<String>,
+    clients: usize,
+) {
+    let contents = match std::fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(err) => fail(format!("Failed to read {}: {}", file.display(), err)),
+    };
+    let mut exchanges = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Exchange>(line) {
+            Ok(exchange) => exchanges.push((index + 1, exchange)),
+            Err(err) => fail(format!("{} line {} is not a recorded request: {}", file.display(), index + 1, err)),
+        }
+    }
+    if exchanges.is_empty() {
+        fail(format!("{} has no recorded requests", file.display()));
+    }
+
+    let target = target.trim_end_matches('/').to_string();
+    let exchanges = std::sync::Arc::new(exchanges);
+    let started = Instant::now();
+    let handles: Vec<_> = (0..clients.max(1))
+        .map(|client_index| {
+            tokio::spawn(replay_client(
+                client_index,
+                target.clone(),
+                exchanges.clone(),
+                password.clone(),
+                token.clone(),
+            ))
+        })
+        .collect();
+
+    let mut mismatches = Vec::new();
+    let mut latencies_ms = Vec::new();
+    for handle in handles {
+        match handle.await {
+            Ok(outcome) => {
+                mismatches.extend(outcome.mismatches);
+                latencies_ms.extend(outcome.latencies_ms);
+            }
+            Err(err) => fail(format!("A replay client crashed: {}", err)),
+        }
+    }
+    let elapsed = started.elapsed();
+
+    for mismatch in &mismatches {
+        println!("⚠️ {}", mismatch);
+    }
+    latencies_ms.sort_unstable();
+    let percentile = |p: usize| {
+        latencies_ms
+            .get((latencies_ms.len() * p / 100).min(latencies_ms.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or(0)
+    };
+    println!(
+        "Replayed {} request(s) on {} client(s) in {:.1}s, p50 {}ms, p95 {}ms, max {}ms",
+        latencies_ms.len(),
+        clients.max(1),
+        elapsed.as_secs_f64(),
+        percentile(50),
+        percentile(95),
+        latencies_ms.last().copied().unwrap_or(0)
+    );
+    if !mismatches.is_empty() {
+        fail(format!("{} mismatch(es)", mismatches.len()));
+    }
+    println!("✅ Every response matched the recording");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn same_shape_ignores_values() {
+        assert!(same_shape(
+            &json!({ "status": "success", "data": { "id": 1, "name": "a", "ok": true } }),
+            &json!({ "status": "fail", "data": { "id": 2, "name": "b", "ok": false } }),
+        ));
+    }
+
+    #[test]
+    fn same_shape_treats_null_as_any() {
+        assert!(same_shape(&json!({ "photo": null }), &json!({ "photo": "a.jpg" })));
+        assert!(same_shape(&json!({ "photo": "a.jpg" }), &json!({ "photo": null })));
+    }
+
+    #[test]
+    fn same_shape_compares_first_array_items() {
+        assert!(same_shape(&json!([{ "id": 1 }]), &json!([{ "id": 2 }, { "other": 3 }])));
+        assert!(same_shape(&json!([]), &json!([{ "id": 2 }])));
+        assert!(!same_shape(&json!([{ "id": 1 }]), &json!([{ "uuid": "x" }])));
+    }
+
+    #[test]
+    fn same_shape_catches_changed_fields_and_types() {
+        assert!(!same_shape(&json!({ "id": 1 }), &json!({ "id": 1, "extra": 2 })));
+        assert!(!same_shape(&json!({ "id": 1 }), &json!({ "uuid": 1 })));
+        assert!(!same_shape(&json!({ "id": 1 }), &json!({ "id": "1" })));
+        assert!(!same_shape(&json!({ "data": [] }), &json!({ "data": {} })));
+    }
+
+    #[test]
+    fn fills_every_redacted_password_field() {
+        let mut body = json!({
+            "username": "kakky",
+            "password": REDACTED,
+            "current_password": REDACTED,
+            "new_password": REDACTED,
+            "token": REDACTED,
+        });
+        fill_passwords(&mut body, "hunter22");
+        assert_eq!(
+            body,
+            json!({
+                "username": "kakky",
+                "password": "hunter22",
+                "current_password": "hunter22",
+                "new_password": "hunter22",
+                "token": REDACTED,
+            })
+        );
+    }
+}
@@ -1,14 +1,16 @@
+use crate::request_id;
 use axum::body::Body;
 use axum::response::Response;
 use axum::{http::StatusCode, response::IntoResponse};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize, Serializer};
 use serde_json::{json, Value};
+use utoipa::ToSchema;
 use uuid::Uuid;
-use axum::extract::{rejection::JsonRejection, FromRequest, MatchedPath, Request, State};
+use axum::extract::{rejection::JsonRejection, FromRequest};
 
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct PostResponse {
     pub post_id: Uuid,
     pub author_id: Uuid,
@@ -22,7 +24,7 @@ pub struct PostResponse {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub enum Status {
     #[serde(rename = "success")]
     Success,
@@ -32,11 +34,13 @@ pub enum Status {
     Error,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GeneralResponse {
     pub status: Status,
     pub message: String,
+    // an empty array when there is nothing to send
     #[serde(serialize_with = "serialize_option_value")]
+    #[schema(value_type = Object)]
     pub data: Option<Value>,
 }
 
@@ -90,7 +94,8 @@ impl IntoResponse for ApiError {
                 "Internal server error".to_string(),
                 Status::Error,
             ),
-            ApiError::JsonRejection(_) => {
+            ApiError::JsonRejection(rejection) => {
+                tracing::debug!("json rejected: {}", rejection.body_text());
                 (StatusCode::INTERNAL_SERVER_ERROR,
                 "invalid json".to_string(),
                 Status::Error)
@@ -102,11 +107,16 @@ impl IntoResponse for ApiError {
             message,
             data: None,
         };
+        // quoted in bug reports so the failing request can be found in the logs
+        let mut body = json!(json_response);
+        if let Some(request_id) = request_id::current() {
+            body["request_id"] = json!(request_id);
+        }
 
         Response::builder()
             .status(status)
             .header("Content-Type", "application/json")
-            .body(Body::from(json!(json_response).to_string()))
+            .body(Body::from(body.to_string()))
             .unwrap()
     }
 }
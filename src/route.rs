@@ -1,41 +1,144 @@
 use crate::{
-    handlers::{
-        create_post, delete_post, get_all_posts, get_all_users, get_profile, is_logged_in,
-        login_user_handler, logout_handler, react_to_post, register_user_handler,
-    },
-    session_auth::auth,
+    admin, audit,
+    csrf::{self, csrf},
+    deprecation::deprecated,
+    exports, handlers, health, invites, magic_link,
+    metrics::{self, track},
+    oidc,
+    openapi::{self, ApiDoc, DocumentJson},
+    password_reset, pow,
+    rbac::{require_permission, Permission},
+    recorder::record,
+    session_auth::{auth, require_scope, require_session},
+    tokens::Scope,
     AppState,
 };
-use axum::{
-    middleware,
-    routing::{delete, get, post},
-    Router,
-};
+use axum::{middleware, routing::get, Extension, Router};
 use std::sync::Arc;
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+// the current api, a breaking change to a response shape goes under /v2
+pub const API_PREFIX: &str = "/v1";
 
+// the routes and the OpenAPI document describing them, built together from the
+// #[utoipa::path] attributes so a route can't be missing from the docs
 pub fn create_router(app_state: Arc<AppState>) -> Router {
-    // Define the protected routes
-    let protected_routes = Router::new()
-        .route("/post", post(create_post))
-        .route("/post/:post_id", delete(delete_post))
-        .route("/post/:post_id/react", post(react_to_post))
-        .route("/auth/logout", post(logout_handler))
-        .route("/auth/is_logged_in", post(is_logged_in));
+    // Define the protected routes api tokens may use, each with the scope it needs
+    let token_routes = OpenApiRouter::new()
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(handlers::create_post))
+                .routes(routes!(handlers::delete_post))
+                .route_layer(middleware::from_fn_with_state(Scope::PostsWrite, require_scope)),
+        )
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(handlers::react_to_post))
+                .route_layer(middleware::from_fn_with_state(
+                    Scope::ReactionsWrite,
+                    require_scope,
+                )),
+        )
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(handlers::get_me))
+                .route_layer(middleware::from_fn_with_state(Scope::ProfileRead, require_scope)),
+        );
+
+    // Define the protected routes that need a session login
+    let session_routes = OpenApiRouter::new()
+        .routes(routes!(handlers::logout_handler))
+        .routes(routes!(handlers::is_logged_in))
+        .routes(routes!(handlers::get_api_tokens, handlers::create_api_token))
+        .routes(routes!(handlers::delete_api_token))
+        .routes(routes!(handlers::delete_me))
+        .routes(routes!(handlers::change_password))
+        .routes(routes!(exports::get_exports, exports::request_export))
+        .routes(routes!(oidc::get_identities))
+        .routes(routes!(oidc::delete_identity))
+        .route_layer(middleware::from_fn(require_session));
+
+    // Define the admin routes, session login plus the ManageUsers permission
+    let admin_routes = OpenApiRouter::new()
+        .routes(routes!(admin::admin_get_users))
+        .routes(routes!(admin::admin_delete_user))
+        .routes(routes!(admin::admin_change_role))
+        .routes(routes!(admin::admin_suspend_user))
+        .routes(routes!(admin::admin_unsuspend_user))
+        .routes(routes!(admin::admin_logout_user))
+        .routes(routes!(admin::admin_force_password_reset))
+        .routes(routes!(invites::admin_get_invites, invites::admin_create_invite))
+        .routes(routes!(invites::admin_delete_invite))
+        .route_layer(middleware::from_fn_with_state(
+            Permission::ManageUsers,
+            require_permission,
+        ))
+        .route_layer(middleware::from_fn(require_session));
+
+    let audit_routes = OpenApiRouter::new()
+        .routes(routes!(audit::admin_get_audit_events))
+        .route_layer(middleware::from_fn_with_state(
+            Permission::ViewAuditLog,
+            require_permission,
+        ))
+        .route_layer(middleware::from_fn(require_session));
 
     // Define the unprotected routes
-    let unprotected_routes = Router::new()
-        .route("/user/:username", get(get_profile))
-        .route("/user/get_all", get(get_all_users))
-        .route("/post/get_all", get(get_all_posts))
-        .route("/auth/login", post(login_user_handler))
-        .route("/auth/register", post(register_user_handler));
+    let unprotected_routes = OpenApiRouter::new()
+        .routes(routes!(handlers::get_profile))
+        .routes(routes!(handlers::get_all_users))
+        .routes(routes!(exports::download_export))
+        .routes(routes!(handlers::get_all_posts))
+        .routes(routes!(handlers::login_user_handler))
+        .routes(routes!(handlers::register_user_handler))
+        .routes(routes!(password_reset::reset_password))
+        .routes(routes!(pow::get_challenge))
+        .routes(routes!(csrf::get_csrf_token))
+        .routes(routes!(magic_link::request_magic_link))
+        .routes(routes!(magic_link::verify_magic_link))
+        .routes(routes!(oidc::get_oidc_providers))
+        .routes(routes!(oidc::oidc_login))
+        .routes(routes!(oidc::oidc_callback));
 
     // Apply the middleware layer to protected routes
-    let protected_routes_with_auth =
-        protected_routes.layer(middleware::from_fn_with_state(app_state.clone(), auth));
+    let protected_routes_with_auth = token_routes
+        .merge(session_routes)
+        .merge(admin_routes)
+        .merge(audit_routes)
+        .layer(middleware::from_fn_with_state(app_state.clone(), auth));
 
-    Router::new()
+    let api = OpenApiRouter::new()
         .merge(protected_routes_with_auth)
-        .merge(unprotected_routes)
+        .merge(unprotected_routes);
+
+    // Define the routes for probes and tooling, these are not versioned
+    let operational_routes = OpenApiRouter::new()
+        .routes(routes!(health::healthz))
+        .routes(routes!(health::readyz))
+        .routes(routes!(metrics::get_metrics))
+        .routes(routes!(openapi::get_openapi));
+
+    let (mut router, document) = OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .nest(API_PREFIX, api.clone())
+        .merge(operational_routes)
+        .split_for_parts();
+    // the paths from before versioning, kept as aliases of /v1 and left out of the docs
+    if app_state.env.legacy_routes {
+        router = router.merge(Router::from(api).layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            deprecated,
+        )));
+    }
+    if app_state.env.api_docs {
+        router = router.route("/docs", get(openapi::get_docs));
+    }
+    let document = openapi::finish(document).to_pretty_json().unwrap();
+
+    router
+        .layer(Extension(DocumentJson(document.into())))
+        .layer(middleware::from_fn_with_state(app_state.clone(), csrf))
+        .layer(middleware::from_fn_with_state(app_state.clone(), record))
+        .layer(middleware::from_fn(track))
         .with_state(app_state)
 }
@@ -1,32 +1,149 @@
+use crate::tokens::Scope;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
 use validator::Validate;
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct RegisterUserSchema {
     pub username: String,
     pub email: String,
     pub password: String,
 }
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct RegisterUserSchemaOptional {
     pub username: Option<String>,
     pub email: Option<String>,
     pub password: Option<String>,
+    // required when REGISTRATION_MODE is invite_only
+    pub invite_code: Option<String>,
+    pub pow: Option<PowSolution>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginUserSchema {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreatePostSchema {
     pub title: String,
     pub content: String,
+    pub pow: Option<PowSolution>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LikePostSchema {
     pub is_like: bool,
 }
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiTokenSchema {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminUserQuery {
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangeRoleSchema {
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SuspendUserSchema {
+    pub reason: String,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub actor_id: Option<Uuid>,
+    pub action: Option<String>,
+    pub target_id: Option<Uuid>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MagicLinkSchema {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MagicLinkQuery {
+    pub token: String,
+    pub expires: i64,
+    pub sig: String,
+}
+
+// the query of an emailed reset link, sent back with the new password
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PasswordResetSchema {
+    pub token: String,
+    pub expires: i64,
+    pub sig: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangePasswordSchema {
+    // not needed by an account that has no password yet
+    pub current_password: Option<String>,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteAccountSchema {
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportDownloadQuery {
+    pub expires: i64,
+    pub sig: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateInviteSchema {
+    pub max_uses: Option<i32>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChallengeQuery {
+    pub purpose: String,
+}
+
+// a challenge from GET /auth/challenge sent back with the nonce that solves it
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PowSolution {
+    pub challenge: String,
+    pub difficulty: u32,
+    pub expires: i64,
+    pub sig: String,
+    pub nonce: String,
+}
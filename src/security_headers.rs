@@ -0,0 +1,73 @@
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+// a header a handler already set wins over the default
+fn set_default(headers: &mut HeaderMap, name: HeaderName, value: &Option<String>) {
+    if let Some(value) = value.as_deref().and_then(|value| HeaderValue::from_str(value).ok()) {
+        headers.entry(name).or_insert(value);
+    }
+}
+
+// every setting can be turned off with an empty value, see Config
+pub async fn security_headers(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut response = next.run(req).await;
+    let config = &data.env;
+    let headers = response.headers_mut();
+    headers
+        .entry(header::X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    if config.hsts_max_age > 0 {
+        headers.entry(header::STRICT_TRANSPORT_SECURITY).or_insert(
+            HeaderValue::from_str(&format!("max-age={}; includeSubDomains", config.hsts_max_age))
+                .unwrap(),
+        );
+    }
+    set_default(headers, header::CONTENT_SECURITY_POLICY, &config.content_security_policy);
+    set_default(headers, header::X_FRAME_OPTIONS, &config.frame_options);
+    set_default(headers, header::REFERRER_POLICY, &config.referrer_policy);
+    set_default(
+        headers,
+        HeaderName::from_static("permissions-policy"),
+        &config.permissions_policy,
+    );
+    response
+}
+
+// uploaded files are served from our origin, so they must never run as a page:
+// a locked down CSP and anything that is not an image is a download
+pub async fn asset_headers(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut response = next.run(req).await;
+    let is_image = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("image/") && !value.starts_with("image/svg"));
+    let headers = response.headers_mut();
+    if let Some(csp) = data
+        .env
+        .asset_content_security_policy
+        .as_deref()
+        .and_then(|value| HeaderValue::from_str(value).ok())
+    {
+        headers.insert(header::CONTENT_SECURITY_POLICY, csp);
+    }
+    if !is_image {
+        headers.insert(header::CONTENT_DISPOSITION, HeaderValue::from_static("attachment"));
+    }
+    response
+}
@@ -0,0 +1,376 @@
+use crate::{
+    accounts,
+    cli::{describe, fail},
+    connect_db,
+    handlers::{insert_post, record_reaction},
+    load_breached_passwords, load_config, password,
+    rbac::Role,
+    schema::RegisterUserSchema,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{seq::index::sample, Rng};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::{collections::HashMap, path::PathBuf};
+use uuid::Uuid;
+
+// a fixture file is either one document with any of these lists, or a bare
+// user object or list of users like test/user1.json
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Fixtures {
+    users: Vec<UserFixture>,
+    profiles: Vec<ProfileFixture>,
+    posts: Vec<PostFixture>,
+    reactions: Vec<ReactionFixture>,
+    follows: Vec<FollowFixture>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserFixture {
+    #[serde(flatten)]
+    account: RegisterUserSchema,
+    role: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileFixture {
+    username: String,
+    bio: Option<String>,
+    // a file name inside ./assets
+    photo: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostFixture {
+    author: String,
+    title: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReactionFixture {
+    username: String,
+    // the title of a post from the fixtures or already in the database
+    post: String,
+    is_like: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct FollowFixture {
+    follower: String,
+    followee: String,
+}
+
+fn read_fixtures(path: &PathBuf) -> Result<Fixtures, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let document: Value = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&contents).map_err(|err| err.to_string())?
+    } else {
+        serde_yaml::from_str(&contents).map_err(|err| err.to_string())?
+    };
+    let document = match document {
+        Value::Array(users) => serde_json::json!({ "users": users }),
+        Value::Object(ref user) if user.contains_key("username") => {
+            serde_json::json!({ "users": [document] })
+        }
+        document => document,
+    };
+    serde_json::from_value(document).map_err(|err| err.to_string())
+}
+
+async fn user_id(pool: &PgPool, username: &str) -> Option<Uuid> {
+    sqlx::query_scalar!("SELECT id FROM users WHERE username = $1", username)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+// fixtures go through the same helpers as the handlers, so passwords are
+// hashed and checked like a real sign up unless allow_weak_passwords is set
+pub async fn load_fixtures(dir: PathBuf, allow_weak_passwords: bool) {
+    let config = load_config();
+    let pool = connect_db(&config).await;
+    let breached = load_breached_passwords(&config);
+
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "json" || ext == "yaml" || ext == "yml")
+            })
+            .collect(),
+        Err(err) => fail(format!("Failed to read {}: {}", dir.display(), err)),
+    };
+    paths.sort();
+
+    // every file is read before anything is inserted, so a post may
+    // reference a user from another file
+    let mut fixtures = Fixtures::default();
+    for path in paths {
+        match read_fixtures(&path) {
+            Ok(file) => {
+                fixtures.users.extend(file.users);
+                fixtures.profiles.extend(file.profiles);
+                fixtures.posts.extend(file.posts);
+                fixtures.reactions.extend(file.reactions);
+                fixtures.follows.extend(file.follows);
+            }
+            Err(err) => fail(format!("{} is not a fixture file: {}", path.display(), err)),
+        }
+    }
+
+    let mut users = 0;
+    for user in &fixtures.users {
+        let role = match &user.role {
+            Some(role) => match Role::from_name(role) {
+                Some(role) => role,
+                None => {
+                    println!("⚠️ Skipped {}: unknown role {}", user.account.username, role);
+                    continue;
+                }
+            },
+            None => Role::User,
+        };
+        match accounts::create_account(
+            &pool,
+            &config,
+            &breached,
+            &user.account,
+            role,
+            !allow_weak_passwords,
+        )
+        .await
+        {
+            Ok(_) => users += 1,
+            Err(err) => println!("⚠️ Skipped {}: {}", user.account.username, describe(err)),
+        }
+    }
+
+    let mut profiles = 0;
+    for profile in &fixtures.profiles {
+        let Some(id) = user_id(&pool, &profile.username).await else {
+            println!("⚠️ Skipped profile of {}: no such user", profile.username);
+            continue;
+        };
+        if let Some(photo) = &profile.photo {
+            if !PathBuf::from("./assets").join(photo).is_file() {
+                println!("⚠️ Skipped profile of {}: ./assets/{} does not exist", profile.username, photo);
+                continue;
+            }
+        }
+        match sqlx::query!(
+            "UPDATE profiles SET bio = COALESCE($2, bio), photo = COALESCE($3, photo), updated_at = NOW() WHERE user_id = $1",
+            id,
+            profile.bio,
+            profile.photo
+        )
+        .execute(&pool)
+        .await
+        {
+            Ok(_) => profiles += 1,
+            Err(_) => println!("⚠️ Skipped profile of {}: database error", profile.username),
+        }
+    }
+
+    // seeding twice reuses posts with the same author and title
+    let mut post_ids: HashMap<String, Uuid> = HashMap::new();
+    let mut posts = 0;
+    for post in &fixtures.posts {
+        let Some(author_id) = user_id(&pool, &post.author).await else {
+            println!("⚠️ Skipped post {}: no such user {}", post.title, post.author);
+            continue;
+        };
+        let existing = sqlx::query_scalar!(
+            "SELECT id FROM posts WHERE author_id = $1 AND title = $2",
+            author_id,
+            post.title
+        )
+        .fetch_optional(&pool)
+        .await
+        .ok()
+        .flatten();
+        let post_id = match existing {
+            Some(post_id) => post_id,
+            None => match insert_post(&pool, author_id, &post.title, &post.content).await {
+                Ok(post_id) => {
+                    posts += 1;
+                    post_id
+                }
+                Err(err) => {
+                    println!("⚠️ Skipped post {}: {}", post.title, describe(err));
+                    continue;
+                }
+            },
+        };
+        post_ids.insert(post.title.clone(), post_id);
+    }
+
+    let mut reactions = 0;
+    for reaction in &fixtures.reactions {
+        let Some(reactor_id) = user_id(&pool, &reaction.username).await else {
+            println!("⚠️ Skipped reaction of {}: no such user", reaction.username);
+            continue;
+        };
+        let post_id = match post_ids.get(&reaction.post) {
+            Some(post_id) => Some(*post_id),
+            None => sqlx::query_scalar!("SELECT id FROM posts WHERE title = $1 LIMIT 1", reaction.post)
+                .fetch_optional(&pool)
+                .await
+                .ok()
+                .flatten(),
+        };
+        let Some(post_id) = post_id else {
+            println!("⚠️ Skipped reaction of {}: no post titled {}", reaction.username, reaction.post);
+            continue;
+        };
+        match record_reaction(&pool, post_id, reactor_id, reaction.is_like).await {
+            Ok(()) => reactions += 1,
+            Err(err) => println!("⚠️ Skipped reaction of {}: {}", reaction.username, describe(err)),
+        }
+    }
+
+    // there is no follow graph yet, keep the fixtures but say they were ignored
+    for follow in &fixtures.follows {
+        println!(
+            "⚠️ Skipped follow {} -> {}: follows are not supported yet",
+            follow.follower, follow.followee
+        );
+    }
+
+    println!(
+        "✅ Seeded {} user(s), {} profile(s), {} post(s) and {} reaction(s)",
+        users, profiles, posts, reactions
+    );
+}
+
+// samples ranks with probability proportional to 1 / rank^exponent, so a few
+// ranks take most of the draws like authors and popular posts on a real site
+struct Zipf {
+    cumulative: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize, exponent: f64) -> Zipf {
+        let mut total = 0.0;
+        let cumulative = (1..=n)
+            .map(|rank| {
+                total += 1.0 / (rank as f64).powf(exponent);
+                total
+            })
+            .collect();
+        Zipf { cumulative }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        let target = rng.gen::<f64>() * self.cumulative[self.cumulative.len() - 1];
+        self.cumulative
+            .partition_point(|weight| *weight < target)
+            .min(self.cumulative.len() - 1)
+    }
+}
+
+const WORDS: &[&str] = &[
+    "rust", "async", "server", "post", "coffee", "weekend", "release", "bug", "review", "design",
+    "music", "travel", "garden", "database", "cache", "deploy", "morning", "idea", "book", "game",
+    "city", "photo", "update", "question", "thread", "project", "team", "launch", "note", "story",
+];
+
+fn sentence(rng: &mut impl Rng, words: std::ops::Range<usize>, max_len: usize) -> String {
+    let count = rng.gen_range(words);
+    let mut text = (0..count)
+        .map(|_| WORDS[rng.gen_range(0..WORDS.len())])
+        .collect::<Vec<&str>>()
+        .join(" ");
+    text.truncate(max_len);
+    text
+}
+
+// synthetic accounts for load testing. They share one generated password that is
+// hashed once, hashing thousands of passwords would take longer than the inserts
+pub async fn generate(users: usize, posts: usize) {
+    let config = load_config();
+    let pool = connect_db(&config).await;
+    if users == 0 {
+        fail("--users must be at least 1".to_string());
+    }
+
+    let mut bytes = [0u8; 18];
+    OsRng.fill_bytes(&mut bytes);
+    let password = URL_SAFE_NO_PAD.encode(bytes);
+    let password_hash = match password::hash(&config, &password) {
+        Ok(hash) => hash,
+        Err(err) => fail(format!("Failed to hash the password: {}", describe(err))),
+    };
+    // a run tag keeps usernames unique when the generator runs more than once
+    let run = hex::encode(&bytes[..3]);
+    let mut rng = rand::thread_rng();
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(err) => fail(format!("Failed to start a transaction: {}", err)),
+    };
+    let mut user_ids = Vec::with_capacity(users);
+    for index in 0..users {
+        let username = format!("load_{}_{}", run, index);
+        let email = format!("{}@example.test", username);
+        match accounts::insert_user(&mut tx, &username, &email, Some(&password_hash), Role::User).await {
+            Ok(user_id) => user_ids.push(user_id),
+            Err(err) => fail(format!("Failed to create {}: {}", username, describe(err))),
+        }
+    }
+    if let Err(err) = tx.commit().await {
+        fail(format!("Failed to create users: {}", err));
+    }
+    println!("✅ Created {} user(s)", users);
+
+    // most users never post and a handful write most of the posts
+    let authors = Zipf::new(users, 1.1);
+    let mut post_ids = Vec::with_capacity(posts);
+    for _ in 0..posts {
+        let author_id = user_ids[authors.sample(&mut rng)];
+        let title = sentence(&mut rng, 2..6, 50);
+        let content = sentence(&mut rng, 5..60, 400);
+        match insert_post(&pool, author_id, &title, &content).await {
+            Ok(post_id) => post_ids.push(post_id),
+            Err(err) => fail(format!("Failed to create a post: {}", describe(err))),
+        }
+    }
+    // spread the posts over the last 90 days instead of one burst
+    if let Err(err) = sqlx::query!(
+        "UPDATE posts SET created_at = NOW() - random() * INTERVAL '90 days' WHERE id = ANY($1)",
+        &post_ids
+    )
+    .execute(&pool)
+    .await
+    {
+        fail(format!("Failed to backdate posts: {}", err));
+    }
+    sqlx::query!(
+        "UPDATE posts SET updated_at = created_at WHERE id = ANY($1)",
+        &post_ids
+    )
+    .execute(&pool)
+    .await
+    .ok();
+    println!("✅ Created {} post(s)", posts);
+
+    // reactions per post follow a pareto tail, most posts get a few and some go viral
+    let mut reactions = 0;
+    for post_id in &post_ids {
+        let count = ((1.0 / (1.0 - rng.gen::<f64>()).powf(1.0 / 1.2)) as usize - 1).min(users);
+        for index in sample(&mut rng, users, count) {
+            let is_like = rng.gen_bool(0.85);
+            match record_reaction(&pool, *post_id, user_ids[index], is_like).await {
+                Ok(()) => reactions += 1,
+                Err(err) => fail(format!("Failed to record a reaction: {}", describe(err))),
+            }
+        }
+    }
+    println!("✅ Created {} reaction(s)", reactions);
+    println!("Password for every load_{}_* user: {}", run, password);
+}
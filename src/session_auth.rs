@@ -1,22 +1,132 @@
+use crate::audit;
+use crate::client::ClientInfo;
 use crate::model::User;
+use crate::rbac::Permissions;
 use crate::response::ApiError;
+use crate::tokens::{display_prefix, hash_token, Scope};
 use crate::AppState;
 use axum::{
     body::Body,
     extract::{Request, State},
+    http::header::AUTHORIZATION,
     middleware::Next,
     response::IntoResponse,
 };
+use chrono::Utc;
+use serde_json::json;
 use std::sync::Arc;
 use tower_sessions::Session;
 use uuid::Uuid;
 
+// how the caller proved who they are, inserted next to the User extension
+#[derive(Debug, Clone)]
+pub enum Credential {
+    Session,
+    Token { scopes: Vec<Scope> },
+}
+
+fn bearer_token(req: &Request<Body>) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
 pub async fn auth(
     session: Session,
+    client: ClientInfo,
     State(data): State<Arc<AppState>>,
     mut req: Request<Body>,
     next: Next,
 ) -> Result<impl IntoResponse, ApiError> {
+    if let Some(token) = bearer_token(&req) {
+        let api_token = sqlx::query!(
+            "SELECT id, user_id, scopes, expires_at FROM api_tokens WHERE token_hash = $1",
+            hash_token(&token)
+        )
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+        let api_token = match api_token {
+            Some(api_token) => api_token,
+            None => {
+                audit::record(
+                    &data.db,
+                    &client,
+                    None,
+                    "auth.token_rejected",
+                    None,
+                    json!({ "reason": "unknown_token", "prefix": display_prefix(&token) }),
+                )
+                .await;
+                return Err(ApiError::Fail("invalid api token".to_string()));
+            }
+        };
+
+        if let Some(expires_at) = api_token.expires_at {
+            if expires_at <= Utc::now() {
+                audit::record(
+                    &data.db,
+                    &client,
+                    Some(api_token.user_id),
+                    "auth.token_rejected",
+                    Some(api_token.id),
+                    json!({ "reason": "expired" }),
+                )
+                .await;
+                return Err(ApiError::Fail("api token expired".to_string()));
+            }
+        }
+
+        let user = sqlx::query_as!(
+            User,
+            "SELECT * FROM users WHERE id = $1",
+            api_token.user_id
+        )
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?
+        .ok_or_else(|| ApiError::Fail("user unauthorized".to_string()))?;
+
+        if user.deactivated_at.is_some() {
+            return Err(ApiError::Fail("account is deactivated".to_string()));
+        }
+
+        if user.is_suspended() {
+            audit::record(
+                &data.db,
+                &client,
+                Some(user.id),
+                "auth.suspended_access",
+                Some(api_token.id),
+                json!({ "method": "token" }),
+            )
+            .await;
+            return Err(ApiError::Fail("account is suspended".to_string()));
+        }
+
+        sqlx::query!(
+            "UPDATE api_tokens SET last_used_at = NOW() WHERE id = $1",
+            api_token.id
+        )
+        .execute(&data.db)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+        let scopes = api_token
+            .scopes
+            .iter()
+            .filter_map(|scope| Scope::parse(scope))
+            .collect();
+
+        req.extensions_mut().insert(Permissions::for_role(&user.role));
+        req.extensions_mut().insert(user);
+        req.extensions_mut().insert(Credential::Token { scopes });
+        return Ok(next.run(req).await);
+    }
+
     if let Some(user_id) = session
         .get::<Uuid>("user_id")
         .await
@@ -29,9 +139,56 @@ pub async fn auth(
 
         let user = user.ok_or_else(|| ApiError::Fail("user unauthorized".to_string()))?;
 
+        if user.deactivated_at.is_some() {
+            return Err(ApiError::Fail("account is deactivated".to_string()));
+        }
+
+        if user.is_suspended() {
+            audit::record(
+                &data.db,
+                &client,
+                Some(user.id),
+                "auth.suspended_access",
+                Some(user.id),
+                json!({ "method": "session" }),
+            )
+            .await;
+            return Err(ApiError::Fail("account is suspended".to_string()));
+        }
+
+        req.extensions_mut().insert(Permissions::for_role(&user.role));
         req.extensions_mut().insert(user);
+        req.extensions_mut().insert(Credential::Session);
         Ok(next.run(req).await)
     } else {
         Err(ApiError::Fail("user unathorized".to_string()))
     }
 }
+
+// route layer: sessions pass, api tokens need the scope
+pub async fn require_scope(
+    State(scope): State<Scope>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    match req.extensions().get::<Credential>() {
+        Some(Credential::Session) => Ok(next.run(req).await),
+        Some(Credential::Token { scopes, .. }) if scopes.contains(&scope) => {
+            Ok(next.run(req).await)
+        }
+        _ => Err(ApiError::Fail(format!(
+            "api token is missing scope {}",
+            scope.as_str()
+        ))),
+    }
+}
+
+// route layer for routes api tokens must never reach (token management, logout)
+pub async fn require_session(req: Request<Body>, next: Next) -> Result<impl IntoResponse, ApiError> {
+    match req.extensions().get::<Credential>() {
+        Some(Credential::Session) => Ok(next.run(req).await),
+        _ => Err(ApiError::Fail(
+            "this route requires a session login".to_string(),
+        )),
+    }
+}
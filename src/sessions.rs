@@ -0,0 +1,106 @@
+use crate::client::ClientInfo;
+use crate::csrf;
+use crate::response::ApiError;
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tower_sessions::Session;
+use tower_sessions_redis_store::fred::prelude::*;
+use uuid::Uuid;
+
+// every login is remembered in a per user hash so it can be revoked later,
+// the field is the tower_sessions id which is also the RedisStore key
+fn user_sessions_key(user_id: Uuid) -> String {
+    format!("user_sessions:{}", user_id)
+}
+
+pub async fn start_session(
+    session: &Session,
+    redis: &RedisPool,
+    client: &ClientInfo,
+    user_id: Uuid,
+) -> Result<(), ApiError> {
+    session
+        .cycle_id()
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    csrf::rotate_token(session).await?;
+    session
+        .insert("user_id", user_id)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    // lets sensitive routes ask for a recent login
+    session
+        .insert("authenticated_at", Utc::now().timestamp())
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    // save now so the session has an id we can track
+    session
+        .save()
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+
+    let session_id = session.id().ok_or(ApiError::InternalServerError)?;
+    let meta = json!({
+        "created_at": Utc::now(),
+        "ip": client.ip,
+        "user_agent": client.user_agent,
+    })
+    .to_string();
+    redis
+        .hset::<(), _, _>(user_sessions_key(user_id), (session_id.to_string(), meta))
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    Ok(())
+}
+
+pub async fn end_session(session: &Session, redis: &RedisPool) -> Result<(), ApiError> {
+    let user_id = session
+        .get::<Uuid>("user_id")
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if let (Some(user_id), Some(session_id)) = (user_id, session.id()) {
+        redis
+            .hdel::<(), _, _>(user_sessions_key(user_id), session_id.to_string())
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
+    }
+    session
+        .delete()
+        .await
+        .map_err(|_| ApiError::InternalServerError)
+}
+
+// metadata of every active session, oldest first
+pub async fn list_sessions(redis: &RedisPool, user_id: Uuid) -> Result<Vec<Value>, ApiError> {
+    let sessions: HashMap<String, String> = redis
+        .hgetall(user_sessions_key(user_id))
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    let mut sessions: Vec<Value> = sessions
+        .into_values()
+        .filter_map(|meta| serde_json::from_str(&meta).ok())
+        .collect();
+    sessions.sort_by(|a, b| a["created_at"].as_str().cmp(&b["created_at"].as_str()));
+    Ok(sessions)
+}
+
+// deletes every session record of the user, returns how many were revoked
+pub async fn revoke_sessions(redis: &RedisPool, user_id: Uuid) -> Result<usize, ApiError> {
+    let key = user_sessions_key(user_id);
+    let session_ids: Vec<String> = redis
+        .hkeys(&key)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    if !session_ids.is_empty() {
+        redis
+            .del::<(), _>(session_ids.clone())
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
+    }
+    redis
+        .del::<(), _>(key)
+        .await
+        .map_err(|_| ApiError::InternalServerError)?;
+    Ok(session_ids.len())
+}
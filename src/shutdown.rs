@@ -0,0 +1,53 @@
+use crate::AppState;
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use tokio::sync::watch;
+
+// how long each step after the deadline may take, the jobs putting work back,
+// the database pool and the redis connection
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for ctrl+c");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+// on SIGINT or SIGTERM readiness starts failing, and after SHUTDOWN_DELAY_SECS
+// the listener closes and the background jobs are told to stop
+pub async fn on_signal(data: Arc<AppState>, shutdown: watch::Sender<bool>) {
+    wait_for_signal().await;
+    data.draining.store(true, Ordering::Relaxed);
+    tracing::warn!("shutting down, /readyz now fails");
+    tokio::time::sleep(Duration::from_secs(data.env.shutdown_delay_secs)).await;
+    let _ = shutdown.send(true);
+}
+
+// resolves once the listener should stop accepting connections
+pub async fn requested(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+// resolves when in-flight work has had SHUTDOWN_TIMEOUT_SECS to finish
+pub async fn deadline(shutdown: watch::Receiver<bool>, timeout: Duration) {
+    requested(shutdown).await;
+    tokio::time::sleep(timeout).await;
+}
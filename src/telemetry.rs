@@ -0,0 +1,252 @@
+use crate::{
+    config::{Config, LogFormat},
+    recorder,
+    request_id::RequestId,
+};
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, Response},
+};
+use opentelemetry::{
+    global,
+    propagation::Extractor,
+    trace::{Span as _, SpanKind, TraceContextExt, Tracer as _, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Config as TraceConfig, Sampler, Tracer, TracerProvider},
+    Resource,
+};
+use std::{
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+use tower_http::trace::{self, DefaultOnResponse};
+use tracing::{field::Visit, Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    filter::Targets, layer::Context, prelude::*, registry::LookupSpan, EnvFilter, Layer,
+};
+
+static PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+// the spans are only exported when OTEL_EXPORTER_OTLP_ENDPOINT is set, the
+// logs go to stdout either way
+pub fn init(config: &Config) {
+    // checked by Config::init
+    let filter = EnvFilter::try_new(&config.log_filter).unwrap();
+    let logs = match config.log_format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+    .with_filter(filter);
+
+    let tracer = match &config.otlp_endpoint {
+        Some(endpoint) => match exporter(config, endpoint) {
+            Ok(tracer) => Some(tracer),
+            Err(err) => {
+                eprintln!("failed to start the OTLP exporter: {}", err);
+                None
+            }
+        },
+        None => None,
+    };
+    let traces = tracer.clone().map(|tracer| {
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(EnvFilter::try_new(&config.log_filter).unwrap())
+    });
+    // sqlx logs queries as events at debug, they become spans whatever LOG_LEVEL says
+    let queries = tracer.map(|tracer| {
+        QuerySpans { tracer }.with_filter(Targets::new().with_target("sqlx::query", Level::DEBUG))
+    });
+
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(traces)
+        .with(queries)
+        .init();
+}
+
+fn exporter(config: &Config, endpoint: &str) -> Result<Tracer, String> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(format!("{}/v1/traces", endpoint)),
+        )
+        .with_trace_config(
+            TraceConfig::default()
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                    config.otel_sample_ratio,
+                ))))
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    config.otel_service_name.clone(),
+                )])),
+        )
+        .install_batch(runtime::Tokio)
+        .map_err(|err| err.to_string())?;
+    let tracer = provider.tracer("server");
+    global::set_tracer_provider(provider.clone());
+    let _ = PROVIDER.set(provider);
+    Ok(tracer)
+}
+
+// sends the spans still waiting in the batch, called last thing on shutdown
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get() {
+        if let Err(err) = provider.shutdown() {
+            tracing::warn!(error = %err, "failed to flush traces");
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+// the TraceLayer span. It continues the trace of a W3C traceparent header,
+// and every event logged while handling the request carries the request id.
+// Its fields are logged and exported, so the uri is the redacted one
+pub fn make_span(req: &Request<Body>) -> Span {
+    let id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.as_str())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        // login links, export links and oidc codes carry secrets in the query
+        uri = %recorder::redact_uri(req.uri()),
+        request_id = %id,
+        otel.name = %req.method(),
+        otel.kind = "server",
+        http.route = tracing::field::Empty,
+    );
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    if parent.span().span_context().is_valid() {
+        span.set_parent(parent);
+    }
+    span
+}
+
+// left on the response by metrics::track, the router is the only place the
+// route pattern is known
+#[derive(Clone)]
+pub struct MatchedRoute {
+    pub method: String,
+    pub route: String,
+}
+
+// names the request span "GET /post/:post_id" once the route is known, then
+// logs the response like DefaultOnResponse
+#[derive(Clone)]
+pub struct OnResponse;
+
+impl<B> trace::OnResponse<B> for OnResponse {
+    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
+        if let Some(matched) = response.extensions().get::<MatchedRoute>() {
+            span.record("otel.name", format!("{} {}", matched.method, matched.route));
+            span.record("http.route", matched.route.as_str());
+        }
+        DefaultOnResponse::new()
+            .level(Level::INFO)
+            .on_response(response, latency, span);
+    }
+}
+
+// turns each sqlx::query event into a client span under the span that ran the
+// query, backdated by the elapsed time sqlx measured
+struct QuerySpans {
+    tracer: Tracer,
+}
+
+#[derive(Default)]
+struct QueryFields {
+    summary: String,
+    statement: String,
+    elapsed_secs: f64,
+    rows_affected: u64,
+    rows_returned: u64,
+}
+
+impl Visit for QueryFields {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = value.to_string(),
+            "db.statement" => self.statement = value.trim().to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.elapsed_secs = value;
+        }
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        match field.name() {
+            "rows_affected" => self.rows_affected = value,
+            "rows_returned" => self.rows_returned = value,
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+}
+
+impl<S> Layer<S> for QuerySpans
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = QueryFields::default();
+        event.record(&mut fields);
+        // short queries are logged whole in the summary
+        let statement = if fields.statement.is_empty() {
+            fields.summary.clone()
+        } else {
+            fields.statement
+        };
+
+        let end = SystemTime::now();
+        let start = end - Duration::from_secs_f64(fields.elapsed_secs);
+        let parent = Span::current().context();
+        let mut span = self
+            .tracer
+            .span_builder(fields.summary.trim_end_matches(" …").to_string())
+            .with_kind(SpanKind::Client)
+            .with_start_time(start)
+            .with_attributes(vec![
+                KeyValue::new("db.system", "postgresql"),
+                KeyValue::new("db.statement", statement),
+                KeyValue::new("db.rows_affected", fields.rows_affected as i64),
+                KeyValue::new("db.rows_returned", fields.rows_returned as i64),
+            ])
+            .start_with_context(&self.tracer, &parent);
+        span.end_with_timestamp(end);
+    }
+}
@@ -0,0 +1,51 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+pub const TOKEN_PREFIX: &str = "pat_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    #[serde(rename = "posts:write")]
+    PostsWrite,
+    #[serde(rename = "reactions:write")]
+    ReactionsWrite,
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::PostsWrite => "posts:write",
+            Scope::ReactionsWrite => "reactions:write",
+            Scope::ProfileRead => "profile:read",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        match scope {
+            "posts:write" => Some(Scope::PostsWrite),
+            "reactions:write" => Some(Scope::ReactionsWrite),
+            "profile:read" => Some(Scope::ProfileRead),
+            _ => None,
+        }
+    }
+}
+
+// the plaintext token is only ever returned once, the database keeps the sha256
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", TOKEN_PREFIX, hex::encode(bytes))
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// enough of the token to tell them apart in a listing
+pub fn display_prefix(token: &str) -> String {
+    token.chars().take(TOKEN_PREFIX.len() + 8).collect()
+}